@@ -22,10 +22,7 @@ impl History {
 
     /// Increment the history to the next Input
     pub fn next(&mut self) {
-        let max = match self.history.len().checked_sub(1) {
-            Some(n) => n,
-            None => 0,
-        };
+        let max = self.history.len().saturating_sub(1);
         self.index = match self.index.checked_add(1) {
             Some(n) => std::cmp::min(n, max),
             None => max,
@@ -34,10 +31,7 @@ impl History {
 
     /// Decrement the history to the previous Input
     pub fn prev(&mut self) {
-        self.index = match self.index.checked_sub(1) {
-            Some(n) => n,
-            None => 0,
-        }
+        self.index = self.index.saturating_sub(1)
     }
 
     /// Append a new Input to the list and set it to the current input
@@ -53,10 +47,7 @@ impl History {
             self.history.push(Input::new());
         }
 
-        self.index = match self.history.len().checked_sub(1) {
-            Some(n) => n,
-            None => 0,
-        }
+        self.index = self.history.len().saturating_sub(1)
     }
 }
 
@@ -100,10 +91,7 @@ impl Input {
     /// Returns a tuple (data: String, cursor: usize) where the
     /// cursor is always within the provided view.
     pub fn view(&self, width: usize) -> (String, usize) {
-        let quarter = match width.checked_div(4) {
-            Some(m) => m,
-            None => 1,
-        };
+        let quarter = width.checked_div(4).unwrap_or(1);
 
         let max_cols = 3 * quarter;
         let mut want_cols = max_cols;
@@ -112,10 +100,7 @@ impl Input {
             start_pos -= 1;
             if let Some(c) = self.buffer.get(start_pos) {
                 if let Some(w) = UnicodeWidthChar::width(*c) {
-                    want_cols = match want_cols.checked_sub(w) {
-                        Some(new) => new,
-                        None => 0,
-                    }
+                    want_cols = want_cols.saturating_sub(w);
                 }
             }
         }
@@ -139,17 +124,13 @@ impl Input {
 
     /// Delete a character from the buffer and decrement the cursor
     pub fn backspace(&mut self) {
-        loop {
-            if let Some(n) = self.cursor.checked_sub(1) {
-                let removed = self.buffer.remove(n);
-                self.cursor = n;
-                match UnicodeWidthChar::width(removed) {
-                    // Keep backspacing while we're deleting zero-width chars
-                    Some(width) if width == 0 => continue,
-                    _ => break,
-                }
-            } else {
-                break;
+        while let Some(n) = self.cursor.checked_sub(1) {
+            let removed = self.buffer.remove(n);
+            self.cursor = n;
+            match UnicodeWidthChar::width(removed) {
+                // Keep backspacing while we're deleting zero-width chars
+                Some(0) => continue,
+                _ => break,
             }
         }
     }
@@ -157,7 +138,7 @@ impl Input {
     /// Delete a character from the buffer and do not decrement the cursor
     pub fn delete(&mut self) {
         loop {
-            if let None = self.buffer.get(self.cursor) {
+            if self.buffer.get(self.cursor).is_none() {
                 break;
             }
             self.buffer.remove(self.cursor);
@@ -179,30 +160,20 @@ impl Input {
     /// Delete from cursor to end of previous word
     pub fn backspace_word(&mut self) {
         // If we are already on whitespace, back up to the prev word
-        loop {
-            match self.prev_char() {
-                Some(c) => {
-                    if c.is_whitespace() {
-                        self.backspace();
-                    } else {
-                        break;
-                    }
-                }
-                None => break,
+        while let Some(c) = self.prev_char() {
+            if c.is_whitespace() {
+                self.backspace();
+            } else {
+                break;
             }
         }
 
         // Now delete the word
-        loop {
-            match self.prev_char() {
-                Some(c) => {
-                    if c.is_whitespace() {
-                        break;
-                    } else {
-                        self.backspace();
-                    }
-                }
-                None => break,
+        while let Some(c) = self.prev_char() {
+            if c.is_whitespace() {
+                break;
+            } else {
+                self.backspace();
             }
         }
     }
@@ -216,16 +187,13 @@ impl Input {
                 }
             }
         }
-        return false;
+        false
     }
 
     /// Move the cursor the the right
     pub fn move_right(&mut self, num: usize) {
-        loop {
-            match self.cursor.checked_add(num) {
-                Some(n) => self.set_cursor(n),
-                None => break,
-            };
+        while let Some(n) = self.cursor.checked_add(num) {
+            self.set_cursor(n);
             if !self.is_zero_width(self.cursor) {
                 break;
             }
@@ -235,10 +203,7 @@ impl Input {
     /// Move the cursor to the left
     pub fn move_left(&mut self, num: usize) {
         loop {
-            self.cursor = match self.cursor.checked_sub(num) {
-                Some(n) => n,
-                None => 0,
-            };
+            self.cursor = self.cursor.saturating_sub(num);
             if self.cursor == 0 || !self.is_zero_width(self.cursor) {
                 break;
             }
@@ -4,21 +4,29 @@ mod tab;
 mod tailview;
 #[allow(dead_code)]
 mod util;
+mod who;
 
 #[macro_use]
 extern crate clap;
 use chrono::{Local, Timelike};
 use clap::App;
+use crossbeam_channel::{Select, TryRecvError};
 use crossbeam_utils::thread;
-use icb::{packets, Command, Config};
+use icb::{
+    Command, CommandOutput, Config, DisconnectReason, Encoding, GroupStatus, NoBeepMode, Proxy,
+    ReconnectPolicy, StatusCategory, TlsConfig,
+};
+use std::fs::File;
 use std::io::{self, Write};
-use std::sync::mpsc::TryRecvError;
+use std::path::PathBuf;
+use std::sync::Mutex;
 use std::time::Duration;
 use termion::clear;
 use termion::cursor::Goto;
 use termion::event::Key;
 use termion::raw::IntoRawMode;
 use termion::screen::AlternateScreen;
+use tracing_subscriber::filter::LevelFilter;
 use tui::backend::TermionBackend;
 use tui::layout::{Constraint, Direction, Layout};
 use tui::widgets::{Block, Borders, Paragraph, Text, Widget};
@@ -50,15 +58,75 @@ fn timestamp() -> String {
     format!("{:02}:{:02}", now.hour(), now.minute())
 }
 
+/// Render command output into a line for the Status tab.
+/// Returns `None` for output that doesn't need displaying.
+fn format_cmdout(output: &CommandOutput) -> Option<String> {
+    match output {
+        CommandOutput::Output(text) => Some(text.clone()),
+        CommandOutput::WhoHeader => Some(who::header()),
+        CommandOutput::WhoLine(user) => Some(who::row(user)),
+        CommandOutput::GroupHeader { name, topic } => Some(format!(
+            "Group: {} {}",
+            name,
+            topic.as_deref().unwrap_or_default()
+        )),
+        CommandOutput::End(_) | CommandOutput::CommandHeader => None,
+    }
+}
+
+/// How often to check whether the terminal was resized while there's nothing else to do.
+const RESIZE_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+
 fn main() -> Result<(), failure::Error> {
     let clap_yaml = load_yaml!("clap.yml");
     let matches = App::from_yaml(clap_yaml).get_matches();
 
+    if let Some(path) = matches.value_of("debug-log") {
+        let file = match File::create(path) {
+            Ok(file) => file,
+            Err(e) => {
+                eprintln!("Could not open {}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+        tracing_subscriber::fmt()
+            .with_writer(Mutex::new(file))
+            .with_ansi(false)
+            .with_max_level(LevelFilter::TRACE)
+            .init();
+    }
+
     let nickname = matches.value_of("nickname").unwrap().to_string();
     let serverip = matches.value_of("hostname").unwrap().to_string();
-    let port = value_t!(matches, "port", u16).unwrap_or(7326);
-    let group = matches.value_of("group").unwrap().to_string();
+    let tls = if matches.is_present("tls") {
+        Some(TlsConfig {
+            ca_file: matches.value_of("ca-file").map(PathBuf::from),
+            ..Default::default()
+        })
+    } else {
+        None
+    };
+    let default_port = if tls.is_some() { 7327 } else { 7326 };
+    let port = value_t!(matches, "port", u16).unwrap_or(default_port);
+    let mut group = matches.value_of("group").unwrap().to_string();
     let log_default = matches.is_present("log");
+    let reconnect = if matches.is_present("reconnect") {
+        Some(ReconnectPolicy::default())
+    } else {
+        None
+    };
+
+    let proxy = match matches
+        .value_of("proxy")
+        .map(str::parse::<Proxy>)
+        .transpose()
+    {
+        Ok(proxy) => proxy,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
 
     let log_path = home::home_dir().map(|mut p| {
         p.push(".icbc");
@@ -69,12 +137,41 @@ fn main() -> Result<(), failure::Error> {
 
     let config = Config {
         nickname,
-        serverip,
+        login_id: matches.value_of("login-id").map(str::to_string),
+        password: matches.value_of("password").map(str::to_string),
+        serverip: serverip.clone(),
         port,
         group: group.clone(),
+        reconnect,
+        proxy,
+        transcript: matches.value_of("transcript").map(PathBuf::from),
+        replay: matches.value_of("replay").map(PathBuf::from),
+        encoding: matches
+            .value_of("encoding")
+            .and_then(Encoding::from_name)
+            .unwrap_or_default(),
+        tls,
+        ..Default::default()
     };
 
-    let (mut client, mut server) = icb::init(config).unwrap();
+    if matches.is_present("who-only") {
+        match icb::who(config) {
+            Ok(listing) => who::table(&listing).iter().for_each(|l| println!("{}", l)),
+            Err(e) => {
+                eprintln!("Could not list users on {}:{}: {}", serverip, port, e);
+                std::process::exit(1);
+            }
+        }
+        return Ok(());
+    }
+
+    let (mut client, mut server) = match icb::init(config) {
+        Ok(session) => session,
+        Err(e) => {
+            eprintln!("Could not connect to {}:{}: {}", serverip, port, e);
+            std::process::exit(1);
+        }
+    };
 
     // Configure the terminal...
     let stdout = io::stdout().into_raw_mode()?;
@@ -97,8 +194,18 @@ fn main() -> Result<(), failure::Error> {
         });
 
         let mut done = false;
+        let mut connected = true;
         let mut termsize = terminal.size().unwrap();
         while !done {
+            // Sleep until there's a message from the server or input from the user. Neither
+            // is taken from its channel yet, that's done below.
+            let mut sel = Select::new();
+            sel.recv(events.receiver());
+            if connected {
+                sel.recv(&client.msg_r);
+            }
+            sel.ready_timeout(RESIZE_CHECK_INTERVAL).ok();
+
             // Check if the terminal has resized and redraw if needed
             let newtermsize = terminal.size().unwrap();
             let mut redraw = newtermsize != termsize;
@@ -106,62 +213,110 @@ fn main() -> Result<(), failure::Error> {
             termsize = newtermsize;
 
             // Handle any communication with the backend before drawing the next screen.
-            if let Ok(m) = client.msg_r.try_recv() {
+            let received = client.msg_r.try_recv();
+            if let Err(TryRecvError::Disconnected) = received {
+                // The library has finished, stop waiting for it.
+                connected = false;
+            }
+            if let Ok(event) = received {
                 redraw = true;
-                let packet_type = m[0].chars().next().unwrap();
-                match packet_type {
-                    packets::T_OPEN => ui.views.add_message(
+                match event {
+                    icb::Event::Open { nick, text } => ui.views.add_message(
                         ChatType::Open(group.clone()),
-                        Message::new(
-                            Local::now(),
-                            MessageType::Open,
-                            m[1].to_string(),
-                            m[2].to_string(),
-                        ),
+                        Message::new(Local::now(), MessageType::Open, nick, text),
                     ),
-                    packets::T_PERSONAL => ui.views.add_message(
-                        ChatType::Personal(m[1].clone()),
-                        Message::new(
-                            Local::now(),
-                            MessageType::Personal,
-                            m[1].to_string(),
-                            m[2].to_string(),
-                        ),
+                    icb::Event::Personal { nick, text } => ui.views.add_message(
+                        ChatType::Personal(nick.clone()),
+                        Message::new(Local::now(), MessageType::Personal, nick, text),
                     ),
-                    packets::T_PROTOCOL => ui
+                    icb::Event::Protocol {
+                        host_id, server_id, ..
+                    } => ui
                         .views
-                        .add_status(format!("==> Connected to {} on {}", m[2], m[1])),
-                    packets::T_STATUS => {
-                        let mtype = MessageType::from_status_str(m[1].as_str());
-                        match mtype {
-                            MessageType::Unknown => ui.views.add_status(format!(
-                                "=> Message '{}' received in unknown category '{}'",
-                                m[2], m[1]
-                            )),
-                            _ => ui.views.add_message(
-                                ChatType::Open(group.clone()),
-                                Message::new(
-                                    Local::now(),
-                                    mtype,
-                                    "[server]".to_string(),
-                                    m[2].to_string(),
-                                ),
-                            ),
+                        .add_status(format!("==> Connected to {} on {}", server_id, host_id)),
+                    icb::Event::Status {
+                        category: StatusCategory::Other(category),
+                        text,
+                    } => ui.views.add_status(format!(
+                        "=> Message '{}' received in unknown category '{}'",
+                        text, category
+                    )),
+                    icb::Event::Status { category, text } => {
+                        // Follow the server into a new group by renaming the group's tab.
+                        if category == StatusCategory::Status {
+                            if let Some(newgroup) = icb::joined_group(&text) {
+                                if newgroup != group {
+                                    if let Err(why) = ui.views.rename_open(newgroup) {
+                                        ui.views.add_status(format!("Logging error: {}", why)).ok();
+                                    }
+                                    group = newgroup.to_string();
+                                }
+                            }
                         }
+
+                        ui.views.add_message(
+                            ChatType::Open(group.clone()),
+                            Message::new(
+                                Local::now(),
+                                MessageType::from(&category),
+                                "[server]".to_string(),
+                                text,
+                            ),
+                        )
                     }
-                    packets::T_BEEP => ui.views.add_message(
-                        ChatType::Personal(m[1].clone()),
+                    icb::Event::Beep { nick } => ui.views.add_message(
+                        ChatType::Personal(nick.clone()),
                         Message::new(
                             Local::now(),
                             MessageType::Beep,
-                            m[1].to_string(),
+                            nick,
                             "*beeps you*".to_string(),
                         ),
                     ),
-                    // XXX: should handle "\x18eNick is already in use\x00" too
-                    _ => ui
+                    icb::Event::Error(text) => ui.views.add_current(Message::new(
+                        Local::now(),
+                        MessageType::Error,
+                        "[server]".to_string(),
+                        text,
+                    )),
+                    icb::Event::CommandOutput(output) => match format_cmdout(&output) {
+                        Some(line) => ui.views.add_status(line),
+                        None => Ok(()),
+                    },
+                    icb::Event::WhoListing(listing) => {
+                        for line in who::table(&listing) {
+                            ui.views.add_status(line).ok();
+                        }
+                        Ok(())
+                    }
+                    icb::Event::Pong { latency, .. } => ui.views.add_status(format!(
+                        "==> Pong from server after {} ms",
+                        latency.as_millis()
+                    )),
+                    icb::Event::Important { category, text } => ui.views.add_important(
+                        "[server]".to_string(),
+                        format!("[{}] {}", category.as_str(), text),
+                    ),
+                    icb::Event::Reconnecting { attempt, delay } => ui.views.add_status(format!(
+                        "==> Connection lost, reconnecting in {} seconds (attempt {})",
+                        delay.as_secs(),
+                        attempt
+                    )),
+                    icb::Event::Reconnected => ui
+                        .views
+                        .add_status(format!("==> Reconnected at {}", timestamp())),
+                    icb::Event::Disconnected(DisconnectReason::Quit) => ui
                         .views
-                        .add_status(format!("msg_r: {} read: {:?}", timestamp(), m)),
+                        .add_status(format!("==> Disconnected at {}", timestamp())),
+                    icb::Event::Disconnected(DisconnectReason::Exit) => ui.views.add_status(
+                        format!("==> Server ended the session at {}", timestamp()),
+                    ),
+                    icb::Event::Disconnected(DisconnectReason::Error(e)) => ui.views.add_status(
+                        format!("==> Connection lost at {}: {}", timestamp(), e),
+                    ),
+                    icb::Event::Disconnected(DisconnectReason::EndOfTranscript) => {
+                        ui.views.add_status("==> End of the transcript".to_string())
+                    }
                 }
                 .ok();
             }
@@ -221,7 +376,7 @@ fn main() -> Result<(), failure::Error> {
                                 let line = ui.input.get_string();
                                 ui.input.new_line();
                                 match line.chars().next() {
-                                    Some(v) if v == '/' => {
+                                    Some('/') => {
                                         let input: Vec<_> = line.split_whitespace().collect();
                                         let cmd = input[0];
 
@@ -288,6 +443,88 @@ fn main() -> Result<(), failure::Error> {
                                             let msg = Command::Name(newname.to_string());
                                             client.cmd_s.send(msg).unwrap();
                                             client.nickname = newname.to_string();
+                                        } else if (cmd == "/group" || cmd == "/g")
+                                            && input.len() == 2
+                                        {
+                                            let msg = Command::Group(input[1].to_string());
+                                            client.cmd_s.send(msg).unwrap();
+                                        } else if cmd == "/topic" && input.len() > 1 {
+                                            // Keep the topic as entered, including any whitespace.
+                                            let topic = line
+                                                .split_once(char::is_whitespace)
+                                                .map(|(_, t)| t.trim_start())
+                                                .unwrap_or_default();
+
+                                            let msg = Command::Topic(topic.to_string());
+                                            client.cmd_s.send(msg).unwrap();
+                                        } else if cmd == "/boot" && input.len() == 2 {
+                                            let msg = Command::Boot(input[1].to_string());
+                                            client.cmd_s.send(msg).unwrap();
+                                        } else if cmd == "/pass" && input.len() == 2 {
+                                            let msg = Command::Pass(input[1].to_string());
+                                            client.cmd_s.send(msg).unwrap();
+                                        } else if cmd == "/status" {
+                                            match GroupStatus::from_args(&input[1..]) {
+                                                Some(status) => client
+                                                    .cmd_s
+                                                    .send(Command::GroupStatus(status))
+                                                    .unwrap(),
+                                                None => {
+                                                    ui.views
+                                                        .add_status(
+                                                            "Usage: /status [r|m|p|i|q|b <arg>|im <arg>|inv <arg>]"
+                                                                .to_string(),
+                                                        )
+                                                        .ok();
+                                                }
+                                            }
+                                        } else if cmd == "/register" && input.len() == 2 {
+                                            let msg = Command::Register(input[1].to_string());
+                                            client.cmd_s.send(msg).unwrap();
+                                        } else if cmd == "/nobeep" {
+                                            let mode = match input[1..] {
+                                                [] => Some(NoBeepMode::On),
+                                                [arg] => NoBeepMode::from_arg(arg),
+                                                _ => None,
+                                            };
+
+                                            match mode {
+                                                Some(mode) => client
+                                                    .cmd_s
+                                                    .send(Command::NoBeep(mode))
+                                                    .unwrap(),
+                                                None => {
+                                                    ui.views
+                                                        .add_status(
+                                                            "Usage: /nobeep [on|off|verbose]"
+                                                                .to_string(),
+                                                        )
+                                                        .ok();
+                                                }
+                                            }
+                                        } else if cmd == "/quote" && input.len() > 1 {
+                                            // Pass the arguments after the command on as entered,
+                                            // including any whitespace between them.
+                                            let args = line
+                                                .split_once(char::is_whitespace)
+                                                .and_then(|(_, rest)| {
+                                                    rest.trim_start().split_once(char::is_whitespace)
+                                                })
+                                                .map_or("", |(_, args)| args.trim_start());
+
+                                            let msg = Command::Raw {
+                                                cmd: input[1].to_string(),
+                                                args: args.to_string(),
+                                            };
+                                            client.cmd_s.send(msg).unwrap();
+                                        } else if cmd == "/ping" && input.len() == 1 {
+                                            client.cmd_s.send(Command::Ping).unwrap();
+                                        } else if (cmd == "/who" || cmd == "/w") && input.len() <= 2
+                                        {
+                                            let group = input.get(1).map(|g| g.to_string());
+
+                                            client.cmd_s.send(Command::Who(group)).unwrap();
+                                            ui.views.switch_to_status();
                                         }
                                     }
                                     _ => {
@@ -372,8 +609,6 @@ fn main() -> Result<(), failure::Error> {
                 )
                 .expect("Failed to position cursor");
                 io::stdout().flush().ok();
-            } else {
-                std::thread::sleep(Duration::from_millis(1));
             }
         }
         server_handle.join().unwrap();
@@ -1,5 +1,7 @@
 use crate::tailview::ViewOptions;
 use chrono::{DateTime, Local};
+use icb::StatusCategory;
+use tui::style::{Color, Modifier, Style};
 
 #[derive(Debug, PartialEq)]
 pub enum MessageType {
@@ -7,7 +9,9 @@ pub enum MessageType {
     Beep,
     Boot,
     Depart,
+    Error,
     Help,
+    Important,
     Name,
     NoBeep,
     Notify,
@@ -21,23 +25,22 @@ pub enum MessageType {
     Warning,
 }
 
-impl MessageType {
-    // Parse status packet strings
-    pub fn from_status_str(s: &str) -> Self {
-        match s {
-            "Arrive" => Self::Arrive,
-            "Boot" => Self::Boot,
-            "Depart" => Self::Depart,
-            "Help" => Self::Help,
-            "Name" => Self::Name,
-            "No-Beep" => Self::NoBeep,
-            "Notify" => Self::Notify,
-            "Sign-off" => Self::SignOff,
-            "Sign-on" => Self::SignOn,
-            "Status" => Self::Status,
-            "Topic" => Self::Topic,
-            "Warning" => Self::Warning,
-            _ => Self::Unknown,
+impl From<&StatusCategory> for MessageType {
+    fn from(category: &StatusCategory) -> Self {
+        match category {
+            StatusCategory::Arrive => Self::Arrive,
+            StatusCategory::Boot => Self::Boot,
+            StatusCategory::Depart => Self::Depart,
+            StatusCategory::Help => Self::Help,
+            StatusCategory::Name => Self::Name,
+            StatusCategory::NoBeep => Self::NoBeep,
+            StatusCategory::Notify => Self::Notify,
+            StatusCategory::SignOff => Self::SignOff,
+            StatusCategory::SignOn => Self::SignOn,
+            StatusCategory::Status => Self::Status,
+            StatusCategory::Topic => Self::Topic,
+            StatusCategory::Warning => Self::Warning,
+            StatusCategory::Other(_) => Self::Unknown,
         }
     }
 }
@@ -89,9 +92,18 @@ impl Message {
             MessageType::Open | MessageType::Personal | MessageType::Beep => {
                 format!("{}: <{}> {}\n", datestr, self.from, self.body)
             }
+            MessageType::Error => format!("{}: [error] {}\n", datestr, self.body),
+            MessageType::Important => format!("{}: !!! {}\n", datestr, self.body),
             _ => format!("{}: {}\n", datestr, self.body),
         };
 
         Some(text)
     }
+
+    pub fn style(&self) -> Style {
+        match self.message_type {
+            MessageType::Important => Style::default().fg(Color::Red).modifier(Modifier::BOLD),
+            _ => Style::default(),
+        }
+    }
 }
@@ -53,7 +53,7 @@ impl Tab {
         Ok(())
     }
 
-    fn title(&self) -> Text {
+    fn title(&self) -> Text<'_> {
         let mut modifier = Modifier::empty();
         if self.has_unread {
             modifier.insert(Modifier::BOLD);
@@ -83,8 +83,7 @@ pub struct Tabs {
 
 impl Tabs {
     pub fn new() -> Tabs {
-        let mut v = Vec::new();
-        v.push(Tab::new(ChatType::Status(STATUS.to_string()), None));
+        let v = vec![Tab::new(ChatType::Status(STATUS.to_string()), None)];
 
         Tabs {
             tabs: v,
@@ -128,6 +127,22 @@ impl Tabs {
         log_res
     }
 
+    /// Rename the tab for the group chat after changing groups. If there is no such
+    /// tab yet, the next message for the new group will open one.
+    pub fn rename_open(&mut self, group: &str) -> Result<(), Error> {
+        if let Some(tab) = self
+            .tabs
+            .iter_mut()
+            .find(|t| matches!(t.tab_type, ChatType::Open(_)))
+        {
+            tab.tab_type = ChatType::Open(group.to_string());
+            tab.title = group.to_string();
+            tab.view.rename(group)
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn command_for_current(&self, msg: &str) -> Command {
         if let Some(tab) = self.tabs.get(self.current_tab) {
             tab.command(msg)
@@ -151,6 +166,10 @@ impl Tabs {
         }
     }
 
+    pub fn switch_to_status(&mut self) {
+        self.switch_to(ChatType::Status(STATUS.to_string()));
+    }
+
     pub fn add_status(&mut self, msg: String) -> Result<(), String> {
         self.add_message(
             ChatType::Status(STATUS.to_string()),
@@ -163,14 +182,33 @@ impl Tabs {
         )
     }
 
-    pub fn draw_titles<B>(&mut self, mut frame: &mut Frame<B>, area: Rect)
+    // Important messages go to the status tab and, so they're not missed, the current tab too.
+    pub fn add_important(&mut self, from: String, msg: String) -> Result<(), String> {
+        let status = ChatType::Status(STATUS.to_string());
+        let on_status = self
+            .tabs
+            .get(self.current_tab)
+            .is_some_and(|t| t.tab_type == status);
+
+        if !on_status {
+            self.add_current(Message::new(
+                Local::now(),
+                MessageType::Important,
+                from.clone(),
+                msg.clone(),
+            ))?;
+        }
+        self.add_message(
+            status,
+            Message::new(Local::now(), MessageType::Important, from, msg),
+        )
+    }
+
+    pub fn draw_titles<B>(&mut self, frame: &mut Frame<B>, area: Rect)
     where
         B: Backend,
     {
-        let n: u32 = match u32::try_from(self.tabs.len()) {
-            Ok(l) => l,
-            Err(_) => u32::max_value(),
-        };
+        let n: u32 = u32::try_from(self.tabs.len()).unwrap_or(u32::MAX);
         let constraints = vec![Constraint::Ratio(1, n); n as usize];
 
         Layout::default()
@@ -191,17 +229,17 @@ impl Tabs {
                 Paragraph::new([self.tabs[i].title()].iter())
                     .block(Block::default().borders(borders))
                     .alignment(Alignment::Center)
-                    .render(&mut frame, *area);
+                    .render(frame, *area);
             });
     }
 
-    pub fn draw_current<B>(&mut self, mut frame: &mut Frame<B>, area: Rect)
+    pub fn draw_current<B>(&mut self, frame: &mut Frame<B>, area: Rect)
     where
         B: Backend,
     {
         if let Some(tab) = self.tabs.get_mut(self.current_tab) {
             tab.has_unread = false;
-            tab.view.draw(&mut frame, area);
+            tab.view.draw(frame, area);
         }
     }
 
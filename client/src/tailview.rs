@@ -50,10 +50,7 @@ impl Line {
             w += next_w + 1;
         });
 
-        match u16::try_from(h) {
-            Ok(res) => res,
-            Err(_) => u16::max_value(),
-        }
+        u16::try_from(h).unwrap_or(u16::MAX)
     }
 }
 
@@ -95,13 +92,13 @@ pub struct TailView {
 }
 
 impl TailView {
-    pub fn new(name: &String, log_path: Option<PathBuf>) -> TailView {
+    pub fn new(name: &str, log_path: Option<PathBuf>) -> TailView {
         TailView {
             history: Vec::with_capacity(1000),
             start: 0,
             max_start: 0,
             options: ViewOptions::new(),
-            name: name.clone(),
+            name: name.to_string(),
             log_path: log_path.clone(),
             log: None,
         }
@@ -110,7 +107,7 @@ impl TailView {
     pub fn add(&mut self, message: Message) {
         if let Some(ref mut log) = self.log {
             if let Some(s) = message.render(&self.options) {
-                log.write_all(&s.as_bytes()).ok();
+                log.write_all(s.as_bytes()).ok();
             }
         }
         self.history.push(Line::new(message));
@@ -173,7 +170,7 @@ impl TailView {
         }
     }
 
-    pub fn draw<B>(&mut self, mut frame: &mut Frame<B>, area: Rect)
+    pub fn draw<B>(&mut self, frame: &mut Frame<B>, area: Rect)
     where
         B: Backend,
     {
@@ -185,14 +182,17 @@ impl TailView {
             .history
             .iter()
             .skip(self.start)
-            .filter_map(|l| l.message.render(&self.options))
-            .map(|s| Text::raw(s))
+            .filter_map(|l| {
+                l.message
+                    .render(&self.options)
+                    .map(|s| Text::styled(s, l.message.style()))
+            })
             .collect();
 
         Paragraph::new(lines.iter())
             .block(b)
             .wrap(true)
-            .render(&mut frame, area);
+            .render(frame, area);
     }
 
     fn new_log(&mut self) -> Result<(), Error> {
@@ -201,13 +201,7 @@ impl TailView {
             path.push(self.name.clone());
             std::fs::create_dir_all(&path)?;
             path.push(Local::now().to_string());
-            self.log = Some(
-                OpenOptions::new()
-                    .create(true)
-                    .append(true)
-                    .write(true)
-                    .open(path)?,
-            );
+            self.log = Some(OpenOptions::new().create(true).append(true).open(path)?);
             Ok(())
         } else {
             Err(Error::new(
@@ -217,6 +211,17 @@ impl TailView {
         }
     }
 
+    /// Change the name of the view, continuing logging (if enabled) in a log for
+    /// the new name.
+    pub fn rename(&mut self, name: &str) -> Result<(), Error> {
+        self.name = name.to_string();
+        if self.log.is_some() {
+            self.new_log()
+        } else {
+            Ok(())
+        }
+    }
+
     pub fn toggle_logging(&mut self) -> Result<(), Error> {
         if self.log.take().is_none() {
             self.new_log()
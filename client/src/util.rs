@@ -1,7 +1,7 @@
 // This is taken from https://github.com/fdehau/tui-rs/blob/master/examples/util/event.rs
 // minus the exit_key handling, it's MIT licensed and: Copyright (c) 2016 Florian Dehau
+use crossbeam_channel::{unbounded, Receiver, TryRecvError};
 use std::io;
-use std::sync::mpsc;
 use std::thread;
 
 use termion::event::Key;
@@ -13,22 +13,20 @@ pub enum Event<I> {
 
 /// A small event handler that wrap termion input events.
 pub struct Events {
-    rx: mpsc::Receiver<Event<Key>>,
+    rx: Receiver<Event<Key>>,
     input_handle: thread::JoinHandle<()>,
 }
 
 impl Events {
     pub fn new() -> Events {
-        let (tx, rx) = mpsc::channel();
+        let (tx, rx) = unbounded();
         let input_handle = {
             let tx = tx.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    if let Ok(key) = evt {
-                        if tx.send(Event::Input(key)).is_err() {
-                            return;
-                        }
+                for key in stdin.keys().flatten() {
+                    if tx.send(Event::Input(key)).is_err() {
+                        return;
                     }
                 }
             })
@@ -36,7 +34,12 @@ impl Events {
         Events { rx, input_handle }
     }
 
-    pub fn next(&self) -> Result<Event<Key>, mpsc::TryRecvError> {
+    pub fn next(&self) -> Result<Event<Key>, TryRecvError> {
         self.rx.try_recv()
     }
+
+    /// The channel on which input events arrive, e.g. to wait for them with `Select`.
+    pub fn receiver(&self) -> &Receiver<Event<Key>> {
+        &self.rx
+    }
 }
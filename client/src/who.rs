@@ -0,0 +1,70 @@
+use chrono::{Local, TimeZone};
+use icb::{UserInfo, WhoListing};
+
+/// Format an idle time in seconds as a short human readable string.
+fn format_idle(secs: u64) -> String {
+    if secs < 60 {
+        format!("{}s", secs)
+    } else if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}h{}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+/// Format a login time (seconds since the epoch) as 'HH:MM'.
+fn format_login_time(secs: i64) -> String {
+    match Local.timestamp_opt(secs, 0).single() {
+        Some(t) => t.format("%H:%M").to_string(),
+        None => "?".to_string(),
+    }
+}
+
+/// Column headers for a who-listing.
+pub fn header() -> String {
+    format!(
+        "  {:<16} {:>6} {:>7}  {}",
+        "Nickname", "Idle", "Sign-on", "Account"
+    )
+}
+
+/// A single row in a who-listing; moderators are marked with a '*'.
+pub fn row(user: &UserInfo) -> String {
+    format!(
+        "{} {:<16} {:>6} {:>7}  {}@{}{}",
+        if user.moderator { "*" } else { " " },
+        user.nickname,
+        format_idle(user.idle),
+        format_login_time(user.login_time),
+        user.username,
+        user.hostname,
+        if user.registered { "" } else { " (nr)" },
+    )
+}
+
+/// Render a complete who-listing as a table, one line per entry.
+pub fn table(listing: &WhoListing) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut total = 0;
+
+    for group in &listing.groups {
+        if !group.name.is_empty() {
+            lines.push(format!(
+                "Group: {}  Mod: {}  Topic: {}",
+                group.name,
+                group.moderator.as_deref().unwrap_or("(None)"),
+                group.topic.as_deref().unwrap_or("(None)"),
+            ));
+        }
+        lines.push(header());
+        lines.extend(group.members.iter().map(row));
+        total += group.members.len();
+    }
+
+    lines.push(format!(
+        "Total: {} users in {} groups",
+        total,
+        listing.groups.len()
+    ));
+    lines
+}
@@ -0,0 +1,382 @@
+use std::io::{self, Read};
+
+#[cfg(any(feature = "server", feature = "testing"))]
+use crate::packets::ClientPacket;
+
+/// Length byte which indicates an extended packet: 255 bytes of data which
+/// continue in the next packet.
+pub const EXTENDED: u8 = 0;
+
+/// Number of data bytes in a packet of which the length byte is `EXTENDED`.
+pub const EXTENDED_LEN: usize = 255;
+
+/// Default maximum size of a reassembled extended packet.
+pub const DEFAULT_MAX_PACKET_LEN: usize = 16 * 1024;
+
+/// A single packet as read from the stream, without its length byte.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    pub data: Vec<u8>,
+    /// Set for extended packets, whose data continues in the next frame.
+    pub extended: bool,
+}
+
+/// Splits a stream of bytes into ICB packets.
+///
+/// Bytes are accumulated with `fill()` or `push()`, after which every complete packet
+/// can be taken with `next_frame()`. A partially received packet is kept until the
+/// rest of it arrives, so the data may be split up at any point.
+#[derive(Debug, Default)]
+pub struct Framer {
+    buffer: Vec<u8>,
+}
+
+impl Framer {
+    pub fn new() -> Framer {
+        Framer {
+            buffer: Vec::with_capacity(512),
+        }
+    }
+
+    /// Append `data` to the bytes received so far.
+    pub fn push(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Read whatever data is available from `reader`. Returns the number of bytes
+    /// read, which is 0 once the end of the stream has been reached.
+    /// Errors from `reader` (including `WouldBlock` for non-blocking readers) are
+    /// passed on; no data is lost in that case.
+    pub fn fill<R: Read>(&mut self, reader: &mut R) -> io::Result<usize> {
+        let mut chunk = [0; 512];
+        let nbytes = loop {
+            match reader.read(&mut chunk) {
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                res => break res?,
+            }
+        };
+
+        self.push(&chunk[..nbytes]);
+        Ok(nbytes)
+    }
+
+    /// Take the next complete packet, if one has been received.
+    pub fn next_frame(&mut self) -> Option<Frame> {
+        let (&len, rest) = self.buffer.split_first()?;
+
+        let (len, extended) = match len {
+            EXTENDED => (EXTENDED_LEN, true),
+            n => (n as usize, false),
+        };
+
+        if rest.len() < len {
+            // Wait for the rest of the packet.
+            return None;
+        }
+
+        let data = rest[..len].to_vec();
+        self.buffer.drain(..=len);
+
+        Some(Frame { data, extended })
+    }
+
+    /// Number of bytes received which are not part of a complete packet yet.
+    pub fn pending(&self) -> usize {
+        self.buffer.len()
+    }
+}
+
+/// Combines the parts of extended packets into a single packet.
+///
+/// Frames are passed to `add()` in the order they were received; a regular packet is
+/// returned as is, while the parts of an extended packet are collected until the final
+/// (non-extended) part arrives.
+#[derive(Debug)]
+pub struct Reassembler {
+    packet: Vec<u8>,
+    max_len: usize,
+    /// Set while the remaining parts of an oversized packet are discarded.
+    discarding: bool,
+}
+
+impl Reassembler {
+    /// Create a `Reassembler` for packets of at most `max_len` bytes. A `max_len` below
+    /// `EXTENDED_LEN` is raised to it, so regular packets are never rejected.
+    pub fn new(max_len: usize) -> Reassembler {
+        Reassembler {
+            packet: Vec::new(),
+            max_len: max_len.max(EXTENDED_LEN),
+            discarding: false,
+        }
+    }
+
+    /// Drop any partially received packet, e.g. after reconnecting.
+    pub fn reset(&mut self) {
+        self.packet.clear();
+        self.discarding = false;
+    }
+
+    /// Add a frame, returning the complete packet once its last part has been added.
+    /// Packets exceeding the maximum size are dropped and an `InvalidData` error
+    /// is returned once, after which the next packet is handled normally.
+    pub fn add(&mut self, frame: Frame) -> io::Result<Option<Vec<u8>>> {
+        if self.discarding {
+            self.discarding = frame.extended;
+            return Ok(None);
+        }
+
+        if self.packet.len() + frame.data.len() > self.max_len {
+            self.packet.clear();
+            self.discarding = frame.extended;
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Packet exceeds the maximum size of {} bytes", self.max_len),
+            ));
+        }
+
+        self.packet.extend_from_slice(&frame.data);
+        if frame.extended {
+            Ok(None)
+        } else {
+            Ok(Some(std::mem::take(&mut self.packet)))
+        }
+    }
+}
+
+/// Reads the packets a client sends, for the server side of a connection.
+#[cfg(any(feature = "server", feature = "testing"))]
+#[derive(Debug)]
+pub(crate) struct ClientReader<R> {
+    reader: R,
+    framer: Framer,
+    reassembler: Reassembler,
+}
+
+#[cfg(any(feature = "server", feature = "testing"))]
+impl<R: Read> ClientReader<R> {
+    pub(crate) fn new(reader: R, max_len: usize) -> ClientReader<R> {
+        ClientReader {
+            reader,
+            framer: Framer::new(),
+            reassembler: Reassembler::new(max_len),
+        }
+    }
+
+    /// Wait for the next packet. Packets which can't be decoded result in an
+    /// `InvalidData` error, after which reading can continue.
+    pub(crate) fn read(&mut self) -> io::Result<ClientPacket> {
+        loop {
+            while let Some(frame) = self.framer.next_frame() {
+                if let Some(message) = self.reassembler.add(frame)? {
+                    return Ok(ClientPacket::decode(&message)?);
+                }
+            }
+
+            if self.framer.fill(&mut self.reader)? == 0 {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A regular packet with the given data.
+    fn packet(data: &[u8]) -> Vec<u8> {
+        let mut v = vec![data.len() as u8];
+        v.extend_from_slice(data);
+        v
+    }
+
+    /// A packet of `len` bytes of data, split into extended parts.
+    fn extended(len: usize) -> (Vec<u8>, Vec<u8>) {
+        let data: Vec<u8> = (0..len).map(|i| b'a' + (i % 26) as u8).collect();
+        let mut v = Vec::new();
+        let mut chunks = data.chunks(EXTENDED_LEN).peekable();
+        while let Some(chunk) = chunks.next() {
+            if chunk.len() == EXTENDED_LEN && chunks.peek().is_some() {
+                v.push(EXTENDED);
+                v.extend_from_slice(chunk);
+            } else {
+                v.extend(packet(chunk));
+            }
+        }
+        (v, data)
+    }
+
+    /// A stream of regular and extended packets, and the packets it should yield.
+    fn stream() -> (Vec<u8>, Vec<Vec<u8>>) {
+        let (long, long_data) = extended(600);
+        let mut stream = packet(b"jprotocol\x00");
+        stream.extend(long);
+        stream.extend(packet(b"bnick\x01hi\x00"));
+        stream.extend(packet(b"n\x00"));
+        (
+            stream,
+            vec![
+                b"jprotocol\x00".to_vec(),
+                long_data,
+                b"bnick\x01hi\x00".to_vec(),
+                b"n\x00".to_vec(),
+            ],
+        )
+    }
+
+    /// Feed `stream` in chunks of the given sizes and collect the packets.
+    fn feed<I: Iterator<Item = usize>>(stream: &[u8], mut sizes: I) -> Vec<Vec<u8>> {
+        let mut framer = Framer::new();
+        let mut reassembler = Reassembler::new(DEFAULT_MAX_PACKET_LEN);
+        let mut packets = Vec::new();
+
+        let mut rest = stream;
+        while !rest.is_empty() {
+            let n = sizes.next().unwrap().clamp(1, rest.len());
+            framer.push(&rest[..n]);
+            rest = &rest[n..];
+
+            while let Some(frame) = framer.next_frame() {
+                if let Some(packet) = reassembler.add(frame).unwrap() {
+                    packets.push(packet);
+                }
+            }
+        }
+
+        assert_eq!(framer.pending(), 0);
+        packets
+    }
+
+    #[test]
+    fn byte_at_a_time() {
+        let (stream, expected) = stream();
+        assert_eq!(feed(&stream, std::iter::repeat(1)), expected);
+    }
+
+    #[test]
+    fn random_chunks() {
+        let (stream, expected) = stream();
+        for seed in 0..100 {
+            let rng = fastrand::Rng::with_seed(seed);
+            let sizes = std::iter::repeat_with(|| rng.usize(1..=300));
+            assert_eq!(feed(&stream, sizes), expected, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn all_at_once() {
+        let (stream, expected) = stream();
+        assert_eq!(feed(&stream, std::iter::once(stream.len())), expected);
+    }
+
+    #[test]
+    fn extended_frame() {
+        let mut framer = Framer::new();
+        framer.push(&[EXTENDED]);
+        framer.push(&[b'x'; EXTENDED_LEN - 1]);
+        assert_eq!(framer.next_frame(), None);
+
+        framer.push(b"x");
+        let frame = framer.next_frame().unwrap();
+        assert!(frame.extended);
+        assert_eq!(frame.data.len(), EXTENDED_LEN);
+        assert_eq!(framer.pending(), 0);
+    }
+
+    #[test]
+    fn partial_trailing_frame_is_kept() {
+        let mut framer = Framer::new();
+        let mut data = packet(b"bhello\x00");
+        data.extend_from_slice(&packet(b"bworld\x00")[..4]);
+        framer.push(&data);
+
+        assert_eq!(framer.next_frame().unwrap().data, b"bhello\x00");
+        assert_eq!(framer.next_frame(), None);
+        assert_eq!(framer.pending(), 4);
+
+        framer.push(b"rld\x00");
+        assert_eq!(framer.next_frame().unwrap().data, b"bworld\x00");
+        assert_eq!(framer.pending(), 0);
+    }
+
+    #[test]
+    fn fill_reads_until_end_of_stream() {
+        let (stream, _) = stream();
+        let mut reader = io::Cursor::new(stream.clone());
+        let mut framer = Framer::new();
+
+        let mut total = 0;
+        loop {
+            match framer.fill(&mut reader).unwrap() {
+                0 => break,
+                n => total += n,
+            }
+        }
+        assert_eq!(total, stream.len());
+        assert_eq!(framer.pending(), stream.len());
+    }
+
+    #[test]
+    fn oversized_packet_is_dropped_and_reassembly_recovers() {
+        let mut framer = Framer::new();
+        let (long, _) = extended(1000);
+        framer.push(&long);
+        framer.push(&packet(b"bnick\x01after\x00"));
+
+        let mut reassembler = Reassembler::new(600);
+        let mut results = Vec::new();
+        while let Some(frame) = framer.next_frame() {
+            results.push(reassembler.add(frame));
+        }
+
+        // Three parts fit, the fourth exceeds the maximum and the rest is discarded.
+        assert_eq!(results.len(), 5);
+        assert!(matches!(results[0], Ok(None)));
+        assert!(matches!(results[1], Ok(None)));
+        let err = results[2].as_ref().unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(matches!(results[3], Ok(None)));
+        assert_eq!(
+            results[4].as_ref().unwrap().as_deref(),
+            Some(&b"bnick\x01after\x00"[..])
+        );
+    }
+
+    #[test]
+    fn maximum_is_at_least_a_regular_packet() {
+        let mut reassembler = Reassembler::new(10);
+        let full = Frame {
+            data: vec![b'x'; EXTENDED_LEN],
+            extended: false,
+        };
+        assert_eq!(reassembler.add(full).unwrap().unwrap().len(), EXTENDED_LEN);
+
+        let part = Frame {
+            data: vec![b'x'; EXTENDED_LEN],
+            extended: true,
+        };
+        let rest = Frame {
+            data: b"x".to_vec(),
+            extended: false,
+        };
+        assert_eq!(reassembler.add(part).unwrap(), None);
+        assert!(reassembler.add(rest).is_err());
+    }
+
+    #[test]
+    fn reset_drops_partial_packet() {
+        let mut reassembler = Reassembler::new(DEFAULT_MAX_PACKET_LEN);
+        let part = Frame {
+            data: vec![b'x'; EXTENDED_LEN],
+            extended: true,
+        };
+        assert_eq!(reassembler.add(part).unwrap(), None);
+
+        reassembler.reset();
+        let packet = Frame {
+            data: b"bnew\x00".to_vec(),
+            extended: false,
+        };
+        assert_eq!(reassembler.add(packet).unwrap(), Some(b"bnew\x00".to_vec()));
+    }
+}
@@ -0,0 +1,254 @@
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tokio::time::{self, Instant};
+use tracing::{info_span, warn, Instrument};
+
+use crate::packets::{self, ClientPacket, ServerPacket};
+use crate::session::{Action, Incoming, Session};
+use crate::transcript::{Direction, Recorder};
+use crate::{next_connection_id, Command, Config, DisconnectReason, Error, Event};
+
+/// An async connection to an ICB server, available with the `tokio` feature.
+///
+/// This is the async counterpart of the `Client` and `Server` pair: the connection is
+/// driven by a task on the tokio runtime, commands are sent with `send()` and events
+/// are received through the `Stream` implementation. The stream ends after the final
+/// `Event::Disconnected`.
+#[derive(Debug)]
+pub struct IcbConnection {
+    pub nickname: String,
+    cmd_s: mpsc::UnboundedSender<Command>,
+    msg_r: mpsc::UnboundedReceiver<Event>,
+}
+
+impl IcbConnection {
+    /// Connect and log in to the configured server, after which the connection is
+    /// handed off to a task spawned on the current runtime.
+    /// `Config::reconnect`, `Config::proxy`, `Config::replay` and `Config::tls` are not
+    /// supported here yet; setting any of them fails with `Error::Unsupported`.
+    pub async fn connect(config: Config) -> Result<IcbConnection, Error> {
+        let unsupported = |option: &str| {
+            Err(Error::Unsupported(format!(
+                "{} is not supported by IcbConnection yet",
+                option
+            )))
+        };
+        if config.reconnect.is_some() {
+            return unsupported("reconnecting");
+        }
+        if config.proxy.is_some() {
+            return unsupported("connecting through a proxy");
+        }
+        if config.replay.is_some() {
+            return unsupported("replaying a transcript");
+        }
+        #[cfg(feature = "tls")]
+        if config.tls.is_some() {
+            return unsupported("TLS");
+        }
+
+        let (cmd_s, cmd_r) = mpsc::unbounded_channel();
+        let (msg_s, msg_r) = mpsc::unbounded_channel();
+
+        let transcript = config
+            .transcript
+            .as_deref()
+            .map(Recorder::create)
+            .transpose()?;
+        let sock = TcpStream::connect((config.serverip.as_str(), config.port)).await?;
+        let mut conn = Connection {
+            sock,
+            incoming: Incoming::new(config.max_packet_len, config.encoding, transcript.clone()),
+            session: Session::new(&config),
+            transcript,
+            cmd_r,
+            msg_s,
+        };
+
+        let span = info_span!(
+            "connection",
+            id = next_connection_id(),
+            server = %format!("{}:{}", config.serverip, config.port)
+        );
+        conn.handshake().instrument(span.clone()).await?;
+        tokio::spawn(conn.run().instrument(span));
+
+        Ok(IcbConnection {
+            nickname: config.nickname,
+            cmd_s,
+            msg_r,
+        })
+    }
+
+    /// Send a command to the server. Fails with `Error::Disconnected` once the session
+    /// has ended.
+    pub async fn send(&mut self, command: Command) -> Result<(), Error> {
+        self.cmd_s.send(command).map_err(|_| Error::Disconnected)
+    }
+}
+
+impl Stream for IcbConnection {
+    type Item = Event;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        self.msg_r.poll_recv(cx)
+    }
+}
+
+/// The task side of an `IcbConnection`, which owns the socket.
+struct Connection {
+    sock: TcpStream,
+    incoming: Incoming,
+    session: Session,
+    transcript: Option<Recorder>,
+    cmd_r: mpsc::UnboundedReceiver<Command>,
+    msg_s: mpsc::UnboundedSender<Event>,
+}
+
+impl Connection {
+    /// Send the encoded `data` to the server.
+    async fn write(&mut self, data: &[u8]) -> Result<(), Error> {
+        self.sock.write_all(data).await?;
+        if let Some(transcript) = &self.transcript {
+            transcript.record(Direction::Sent, data);
+        }
+        self.session.sent(data);
+        Ok(())
+    }
+
+    /// Send an event to the client. A client that has gone away is noticed through
+    /// `cmd_r` instead, so failures are ignored here.
+    fn emit(&self, event: Event) {
+        self.msg_s.send(event).ok();
+    }
+
+    /// Take the next packet that was received in its entirety, if any.
+    fn next_packet(&mut self) -> Result<Option<ServerPacket>, Error> {
+        match self.incoming.next_message()? {
+            Some(message) => Ok(Some(self.incoming.decode(&message)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Pass data read from the socket on to be turned into packets.
+    fn received(&mut self, data: &[u8]) -> Result<(), Error> {
+        if data.is_empty() {
+            return Err(Error::Disconnected);
+        }
+        self.incoming.push(data);
+        Ok(())
+    }
+
+    /// Wait for the next packet from the server.
+    async fn read(&mut self) -> Result<ServerPacket, Error> {
+        let mut chunk = [0; 512];
+        loop {
+            if let Some(packet) = self.next_packet()? {
+                return Ok(packet);
+            }
+            let nbytes = self.sock.read(&mut chunk).await?;
+            self.received(&chunk[..nbytes])?;
+        }
+    }
+
+    // Upon connecting the server sends a protocol packet, after which we log in.
+    async fn handshake(&mut self) -> Result<(), Error> {
+        match self.read().await? {
+            packet @ ServerPacket::Protocol { .. } => {
+                if let Some(event) = Event::from_packet(packet) {
+                    self.emit(event);
+                }
+            }
+            packet => {
+                return Err(Error::Protocol(format!(
+                    "expected a protocol packet, received '{}'",
+                    packet.packet_type()
+                )))
+            }
+        }
+
+        let login_packet = self.session.login(packets::LOGIN_LOGIN)?;
+        self.write(&login_packet).await?;
+        Session::login_response(self.read().await?)
+    }
+
+    /// Pass on the event that resulted from a command or packet to the client and carry
+    /// out the action. Returns the reason to disconnect if the session should end.
+    async fn handle(
+        &mut self,
+        (event, action): (Option<Event>, Action),
+    ) -> Result<Option<DisconnectReason>, Error> {
+        if let Some(event) = event {
+            self.emit(event);
+        }
+        match action {
+            Action::Send(data) => {
+                for packet in data {
+                    self.write(&packet).await?;
+                }
+                Ok(None)
+            }
+            Action::Disconnect(reason) => Ok(Some(reason)),
+        }
+    }
+
+    /// Handle commands and packets until either side ends the session.
+    async fn process(&mut self) -> Result<DisconnectReason, Error> {
+        let mut chunk = [0; 512];
+        loop {
+            // Handle everything that was received before waiting for more.
+            loop {
+                match self.next_packet() {
+                    Ok(Some(packet)) => {
+                        let outcome = self.session.packet(packet)?;
+                        if let Some(reason) = self.handle(outcome).await? {
+                            return Ok(reason);
+                        }
+                    }
+                    Ok(None) => break,
+                    // An invalid packet is skipped, the connection itself is still usable.
+                    Err(Error::Protocol(e)) => warn!(error = %e, "skipping invalid packet"),
+                    Err(e) => return Err(e),
+                }
+            }
+
+            let keepalive = self.session.keepalive_deadline().map(Instant::from_std);
+
+            tokio::select! {
+                command = self.cmd_r.recv() => {
+                    // Without a client there's nobody left to talk to.
+                    let command = match command {
+                        Some(command) => command,
+                        None => return Ok(DisconnectReason::Quit),
+                    };
+                    let outcome = self.session.handle_command(command);
+                    if let Some(reason) = self.handle(outcome).await? {
+                        return Ok(reason);
+                    }
+                }
+                nbytes = self.sock.read(&mut chunk) => self.received(&chunk[..nbytes?])?,
+                // Keep the connection alive if nothing was sent for a while.
+                _ = time::sleep_until(keepalive.unwrap_or_else(Instant::now)), if keepalive.is_some() => {
+                    let noop = ClientPacket::NoOp.encode()?;
+                    self.write(&noop).await?;
+                }
+            }
+        }
+    }
+
+    async fn run(mut self) {
+        let reason = match self.process().await {
+            Ok(reason) => reason,
+            Err(e) => DisconnectReason::Error(Arc::new(e)),
+        };
+
+        self.sock.shutdown().await.ok();
+        self.emit(Event::Disconnected(reason));
+    }
+}
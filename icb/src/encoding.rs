@@ -0,0 +1,167 @@
+use std::borrow::Cow;
+use std::convert::TryFrom;
+
+/// Character encodings for the text in packets, as set with `Config::encoding`.
+///
+/// ICB itself doesn't specify an encoding; most clients nowadays send UTF-8, but older
+/// ones send Latin-1 or its Windows variant CP1252.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    /// ISO 8859-1.
+    Latin1,
+    /// Windows-1252, Latin-1 with printable characters in place of the C1 controls.
+    Cp1252,
+    /// UTF-8, falling back to Latin-1 for received fields which aren't valid UTF-8.
+    /// Text is sent as UTF-8.
+    Auto,
+}
+
+/// Characters for the bytes 0x80 to 0x9f in CP1252. The five bytes which are undefined
+/// map to the C1 control with the same value, as they do in Latin-1.
+const CP1252_HIGH: [char; 32] = [
+    '\u{20ac}', '\u{81}', '\u{201a}', '\u{192}', '\u{201e}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{2c6}', '\u{2030}', '\u{160}', '\u{2039}', '\u{152}', '\u{8d}', '\u{17d}', '\u{8f}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201c}', '\u{201d}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{2dc}', '\u{2122}', '\u{161}', '\u{203a}', '\u{153}', '\u{9d}', '\u{17e}', '\u{178}',
+];
+
+impl Encoding {
+    /// The name of the encoding, as accepted by `from_name()`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Latin1 => "latin-1",
+            Encoding::Cp1252 => "cp1252",
+            Encoding::Auto => "auto",
+        }
+    }
+
+    /// Look up an encoding by name, ignoring case. Common aliases such as
+    /// "iso-8859-1" and "windows-1252" are accepted as well.
+    pub fn from_name(name: &str) -> Option<Encoding> {
+        match name.to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" => Some(Encoding::Utf8),
+            "latin-1" | "latin1" | "iso-8859-1" | "iso8859-1" => Some(Encoding::Latin1),
+            "cp1252" | "windows-1252" => Some(Encoding::Cp1252),
+            "auto" => Some(Encoding::Auto),
+            _ => None,
+        }
+    }
+
+    /// Decode received text. Invalid UTF-8 is replaced with U+FFFD, except in
+    /// `Encoding::Auto` where it's decoded as Latin-1 instead.
+    pub fn decode(self, data: &[u8]) -> String {
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(data).into_owned(),
+            Encoding::Latin1 => data.iter().map(|b| char::from(*b)).collect(),
+            Encoding::Cp1252 => data
+                .iter()
+                .map(|b| match b {
+                    0x80..=0x9f => CP1252_HIGH[usize::from(b - 0x80)],
+                    _ => char::from(*b),
+                })
+                .collect(),
+            Encoding::Auto => match std::str::from_utf8(data) {
+                Ok(text) => text.to_string(),
+                Err(_) => Encoding::Latin1.decode(data),
+            },
+        }
+    }
+
+    /// Encode text to send. Characters which can't be represented in the encoding
+    /// are replaced with '?'.
+    pub fn encode(self, text: &str) -> Cow<'_, [u8]> {
+        match self {
+            Encoding::Utf8 | Encoding::Auto => Cow::Borrowed(text.as_bytes()),
+            Encoding::Latin1 => Cow::Owned(
+                text.chars()
+                    .map(|c| u8::try_from(u32::from(c)).unwrap_or(b'?'))
+                    .collect(),
+            ),
+            Encoding::Cp1252 => Cow::Owned(
+                text.chars()
+                    .map(|c| match CP1252_HIGH.iter().position(|h| *h == c) {
+                        Some(i) => 0x80 + i as u8,
+                        None => match u8::try_from(u32::from(c)) {
+                            Ok(b) if !(0x80..=0x9f).contains(&b) => b,
+                            _ => b'?',
+                        },
+                    })
+                    .collect(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cp1252_high_bytes_round_trip() {
+        let data: Vec<u8> = (0x80..=0x9f).collect();
+        let text = Encoding::Cp1252.decode(&data);
+        assert_eq!(text.chars().count(), 32);
+        assert!(text.starts_with("\u{20ac}\u{81}\u{201a}"));
+        assert!(text.ends_with("\u{153}\u{9d}\u{17e}\u{178}"));
+        assert_eq!(Encoding::Cp1252.encode(&text), &data[..]);
+    }
+
+    #[test]
+    fn single_byte_encodings_round_trip() {
+        let data: Vec<u8> = (0x20..=0xff).collect();
+        for encoding in [Encoding::Latin1, Encoding::Cp1252] {
+            let text = encoding.decode(&data);
+            assert_eq!(encoding.encode(&text), &data[..], "{}", encoding.name());
+        }
+        assert_eq!(Encoding::Latin1.decode(b"caf\xe9"), "café");
+        assert_eq!(Encoding::Cp1252.decode(b"caf\xe9"), "café");
+    }
+
+    #[test]
+    fn unmappable_characters_become_question_marks() {
+        assert_eq!(Encoding::Latin1.encode("a€bあ"), &b"a?b?"[..]);
+        assert_eq!(Encoding::Cp1252.encode("a€bあ"), &b"a\x80b?"[..]);
+        // C1 controls which CP1252 uses for printable characters can't be sent.
+        assert_eq!(Encoding::Cp1252.encode("\u{80}\u{81}"), &b"?\x81"[..]);
+        assert_eq!(Encoding::Utf8.encode("あ"), "あ".as_bytes());
+    }
+
+    #[test]
+    fn auto_falls_back_to_latin1() {
+        assert_eq!(Encoding::Auto.decode("café ✓".as_bytes()), "café ✓");
+        assert_eq!(Encoding::Auto.decode(b"caf\xe9"), "café");
+        assert_eq!(Encoding::Utf8.decode(b"caf\xe9"), "caf\u{fffd}");
+        assert_eq!(Encoding::Auto.encode("café"), "café".as_bytes());
+    }
+
+    #[test]
+    fn from_name_accepts_aliases() {
+        let names = [
+            ("utf-8", Encoding::Utf8),
+            ("UTF8", Encoding::Utf8),
+            ("latin-1", Encoding::Latin1),
+            ("Latin1", Encoding::Latin1),
+            ("ISO-8859-1", Encoding::Latin1),
+            ("iso8859-1", Encoding::Latin1),
+            ("cp1252", Encoding::Cp1252),
+            ("Windows-1252", Encoding::Cp1252),
+            ("AUTO", Encoding::Auto),
+        ];
+        for (name, encoding) in names.iter() {
+            assert_eq!(Encoding::from_name(name), Some(*encoding), "{}", name);
+        }
+        assert_eq!(Encoding::from_name("ebcdic"), None);
+
+        for encoding in [
+            Encoding::Utf8,
+            Encoding::Latin1,
+            Encoding::Cp1252,
+            Encoding::Auto,
+        ] {
+            assert_eq!(Encoding::from_name(encoding.name()), Some(encoding));
+        }
+    }
+}
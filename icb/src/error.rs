@@ -0,0 +1,69 @@
+use std::fmt;
+use std::io;
+
+use crate::packets::{DecodeError, EncodeError};
+
+/// Errors that can occur while talking to an ICB server.
+#[derive(Debug)]
+pub enum Error {
+    /// Reading from or writing to the connection failed, or connecting failed entirely.
+    Io(io::Error),
+    /// The server sent data that isn't valid ICB, or a packet other than the one expected.
+    Protocol(String),
+    /// A packet could not be encoded for sending.
+    Encode(EncodeError),
+    /// Setting up TLS failed, e.g. because the CA file could not be loaded.
+    Tls(String),
+    /// The proxy could not be used, e.g. because it refused the connection to the server.
+    Proxy(String),
+    /// The configuration asks for something that isn't supported, e.g. an option of
+    /// `Config` that `IcbConnection` doesn't implement.
+    Unsupported(String),
+    /// The server refused the login.
+    LoginRejected { reason: String },
+    /// The server closed the connection.
+    Disconnected,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{}", e),
+            Error::Protocol(message) => write!(f, "protocol error: {}", message),
+            Error::Encode(e) => write!(f, "{}", e),
+            Error::Tls(message) => write!(f, "TLS error: {}", message),
+            Error::Proxy(message) => write!(f, "proxy error: {}", message),
+            Error::Unsupported(message) => write!(f, "unsupported: {}", message),
+            Error::LoginRejected { reason } => write!(f, "login rejected: {}", reason),
+            Error::Disconnected => write!(f, "connection closed by peer"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Encode(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(e: DecodeError) -> Self {
+        Error::Protocol(e.to_string())
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(e: EncodeError) -> Self {
+        Error::Encode(e)
+    }
+}
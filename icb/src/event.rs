@@ -0,0 +1,166 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::error::Error;
+use crate::packets::{CommandOutput, ServerPacket};
+use crate::who::WhoListing;
+
+/// Events the `Server` sends to the `Client` through the `msg` channels.
+#[derive(Clone, Debug)]
+pub enum Event {
+    /// Protocol information sent by the server upon connecting.
+    Protocol {
+        level: String,
+        host_id: String,
+        server_id: String,
+    },
+    /// A message sent to the group.
+    Open { nick: String, text: String },
+    /// A personal message from another user.
+    Personal { nick: String, text: String },
+    /// A status message from the server, such as users arriving or departing.
+    Status {
+        category: StatusCategory,
+        text: String,
+    },
+    /// An error from the server, e.g. after an invalid command.
+    Error(String),
+    /// An important message from the server, such as a shutdown announcement.
+    Important {
+        category: StatusCategory,
+        text: String,
+    },
+    /// Another user beeped us.
+    Beep { nick: String },
+    /// Output in response to a command.
+    CommandOutput(CommandOutput),
+    /// Reply to a `Command::Ping`, with the time it took for the reply to arrive.
+    Pong { id: String, latency: Duration },
+    /// All users listed in response to a `Command::Who`.
+    WhoListing(WhoListing),
+    /// The connection to the server was lost and will be re-established after `delay`.
+    Reconnecting { attempt: u32, delay: Duration },
+    /// The connection to the server was re-established.
+    Reconnected,
+    /// The connection to the server was closed.
+    Disconnected(DisconnectReason),
+}
+
+/// Why the connection to the server was closed.
+#[derive(Clone, Debug)]
+pub enum DisconnectReason {
+    /// The client sent `Command::Bye`.
+    Quit,
+    /// The server ended the session with an exit packet.
+    Exit,
+    /// The connection failed, e.g. because it was dropped.
+    Error(Arc<Error>),
+    /// All packets of the transcript played back with `Config::replay` were handled.
+    EndOfTranscript,
+}
+
+/// The category of a status message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StatusCategory {
+    Arrive,
+    Boot,
+    Depart,
+    Help,
+    Name,
+    NoBeep,
+    Notify,
+    SignOff,
+    SignOn,
+    Status,
+    Topic,
+    Warning,
+    /// A category not known to this library.
+    Other(String),
+}
+
+impl StatusCategory {
+    /// The category as sent in a status packet.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Arrive => "Arrive",
+            Self::Boot => "Boot",
+            Self::Depart => "Depart",
+            Self::Help => "Help",
+            Self::Name => "Name",
+            Self::NoBeep => "No-Beep",
+            Self::Notify => "Notify",
+            Self::SignOff => "Sign-off",
+            Self::SignOn => "Sign-on",
+            Self::Status => "Status",
+            Self::Topic => "Topic",
+            Self::Warning => "Warning",
+            Self::Other(s) => s,
+        }
+    }
+}
+
+impl From<&str> for StatusCategory {
+    /// Parse the category of a status packet.
+    fn from(s: &str) -> Self {
+        match s {
+            "Arrive" => Self::Arrive,
+            "Boot" => Self::Boot,
+            "Depart" => Self::Depart,
+            "Help" => Self::Help,
+            "Name" => Self::Name,
+            "No-Beep" => Self::NoBeep,
+            "Notify" => Self::Notify,
+            "Sign-off" => Self::SignOff,
+            "Sign-on" => Self::SignOn,
+            "Status" => Self::Status,
+            "Topic" => Self::Topic,
+            "Warning" => Self::Warning,
+            _ => Self::Other(s.to_string()),
+        }
+    }
+}
+
+impl Event {
+    /// Create an `Event` from a packet received from the server. Returns `None`
+    /// for packets which aren't of interest to the client.
+    pub(crate) fn from_packet(packet: ServerPacket) -> Option<Event> {
+        let event = match packet {
+            ServerPacket::Protocol {
+                level,
+                host_id,
+                server_id,
+            } => Event::Protocol {
+                level,
+                host_id,
+                server_id,
+            },
+            ServerPacket::Open { nickname, message } => Event::Open {
+                nick: nickname,
+                text: message,
+            },
+            ServerPacket::Personal { nickname, message } => Event::Personal {
+                nick: nickname,
+                text: message,
+            },
+            ServerPacket::Status { category, message } => Event::Status {
+                category,
+                text: message,
+            },
+            ServerPacket::Error(message) => Event::Error(message),
+            ServerPacket::Important { category, message } => Event::Important {
+                category,
+                text: message,
+            },
+            ServerPacket::Beep(nickname) => Event::Beep { nick: nickname },
+            ServerPacket::CommandOutput(output) => Event::CommandOutput(output),
+            // These are handled by the `Server` itself.
+            ServerPacket::Login
+            | ServerPacket::Exit
+            | ServerPacket::Ping(_)
+            | ServerPacket::Pong(_)
+            | ServerPacket::NoOp => return None,
+        };
+
+        Some(event)
+    }
+}
@@ -1,299 +1,761 @@
-use std::borrow::Cow;
-use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::io::{Error, ErrorKind};
-use std::str;
+use std::fmt;
+use tracing::trace;
 
-use crate::util::q;
+use crate::encoding::Encoding;
+use crate::event::StatusCategory;
+use crate::who::UserInfo;
 
-/// Not a type indicated by the protocol, but one used in this library.
-pub const T_INVALID: char = '0';
 pub const T_LOGIN: char = 'a';
 pub const T_OPEN: char = 'b';
 pub const T_PERSONAL: char = 'c';
 pub const T_STATUS: char = 'd';
 pub const T_ERROR: char = 'e';
+pub const T_IMPORTANT: char = 'f';
+pub const T_EXIT: char = 'g';
 pub const T_COMMAND: char = 'h';
+pub const T_CMDOUT: char = 'i';
 pub const T_PROTOCOL: char = 'j';
 pub const T_BEEP: char = 'k';
+pub const T_PING: char = 'l';
+pub const T_PONG: char = 'm';
+pub const T_NOOP: char = 'n';
+
+/// Maximum number of bytes of field data in a single packet, which leaves room for
+/// the packet type and the trailing NUL byte within the 255 byte packet size.
+pub const MAX_DATA_LEN: usize = 253;
+
+/// The command field of a login packet selects what to do after logging in:
+///   "login" -- join the group and start chatting
+///   "w"     -- list the users who are online, after which the server ends the session
+pub const LOGIN_LOGIN: &str = "login";
+pub const LOGIN_W: &str = "w";
+
+/// Based on the icbd server implementation the following commands can be issued
+/// with a command packet:
+///   "?"      -- help
+///   "beep"   -- beep
+///   "boot"   -- boot
+///   "g"      -- change group
+///   "m"      -- personal message
+///   "msg"    -- personal message
+///   "name"   -- change name
+///   "nobeep" -- disable beep
+///   "p"      -- set password, registering the nickname
+///   "pass"   -- pass moderator
+///   "status" -- change group settings
+///   "topic"  -- set topic
+///   "w"      -- list users
+pub const CMD_HELP: &str = "?";
+pub const CMD_BEEP: &str = "beep";
+pub const CMD_BOOT: &str = "boot";
+pub const CMD_G: &str = "g";
+pub const CMD_M: &str = "m";
+pub const CMD_MSG: &str = "msg";
+pub const CMD_NAME: &str = "name";
+pub const CMD_NOBEEP: &str = "nobeep";
+pub const CMD_P: &str = "p";
+pub const CMD_PASS: &str = "pass";
+pub const CMD_STATUS: &str = "status";
+pub const CMD_TOPIC: &str = "topic";
+pub const CMD_W: &str = "w";
+
+/// Commands this library supports sending.
+const SUPPORTED_COMMANDS: [&str; 12] = [
+    CMD_BEEP, CMD_BOOT, CMD_G, CMD_M, CMD_MSG, CMD_NAME, CMD_NOBEEP, CMD_P, CMD_PASS, CMD_STATUS,
+    CMD_TOPIC, CMD_W,
+];
+
+/// The first field of a command output packet indicates the type of output:
+///   "co" -- generic command output
+///   "ec" -- end of command output
+///   "wl" -- a line in a who-listing
+///   "wh" -- header for a who-listing
+///   "gh" -- group header in a who-listing
+///   "ch" -- command header
+pub const CMDOUT_CO: &str = "co";
+pub const CMDOUT_EC: &str = "ec";
+pub const CMDOUT_WL: &str = "wl";
+pub const CMDOUT_WH: &str = "wh";
+pub const CMDOUT_GH: &str = "gh";
+pub const CMDOUT_CH: &str = "ch";
+
+/// Errors that can occur while encoding a packet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EncodeError {
+    /// The fields don't fit in a single packet.
+    TooLong(usize),
+    /// A field contains a field separator or NUL byte.
+    InvalidCharacter,
+    /// The command is not supported by this library.
+    UnsupportedCommand(String),
+}
 
-// Generic packet creator. Should really be trait method...
-// That way we can rework all the packets functions below as implementations
-// like:
-// trait IcbPacket {
-//   fn parse();
-//   fn create(packet_type: char, fields: Vec<&str>) -> Vec<u8> { etc };
-// }
-// impl IcbPacket for LoginPacket {
-//   pub fn new()
-// }
-//
-// static PACKETS: [&dyn<IcbPacket>] {
-//     &LoginPacket,
-// }
-// What needs to be considered is whether it's a good idea to allocate
-// all the different packet types upfront?
-
-fn packet_create(packet_type: char, fields: Vec<&str>) -> Vec<u8> {
-    let mut data = fields.join("\x01");
-    let dlen = data.len() + 2; // account for the packet type and NUL byte
-
-    // FIXME: Return a Result indicating success? Or an array of packets?
-    let plen = match u8::try_from(dlen) {
-        Ok(res) => res,
-        Err(_) => {
-            data.truncate(253); // Leave room for packet type and NUL byte
-            255
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::TooLong(len) => write!(
+                f,
+                "packet data of {} bytes exceeds the maximum of {} bytes",
+                len, MAX_DATA_LEN
+            ),
+            EncodeError::InvalidCharacter => {
+                write!(f, "field contains a field separator or NUL byte")
+            }
+            EncodeError::UnsupportedCommand(cmd) => write!(f, "command {} not supported", cmd),
         }
-    };
+    }
+}
 
-    let mut v = Vec::<u8>::with_capacity(dlen + 2);
-    v.push(plen);
-    v.push(packet_type as u8);
-    v.extend_from_slice(data.as_bytes());
-    v.push(0x00);
+impl std::error::Error for EncodeError {}
 
-    q("Created payload", &v).unwrap();
+impl From<EncodeError> for std::io::Error {
+    fn from(e: EncodeError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, e)
+    }
+}
 
-    v
+/// Errors that can occur while decoding a packet.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DecodeError {
+    /// The packet contains no data at all.
+    Empty,
+    /// The packet type is unknown, or not valid in this direction.
+    UnknownType(char),
+    /// A required field is missing.
+    MissingField(&'static str),
+    /// A field could not be parsed.
+    InvalidField(&'static str),
 }
 
-#[allow(unused_variables)]
-fn invalid_packet_create(_fields: Vec<&str>) -> Vec<u8> {
-    panic!("You're attempting to create a packet you're not allowed to send to a server");
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::Empty => write!(f, "empty packet"),
+            DecodeError::UnknownType(t) => write!(f, "invalid packet of type {}", t),
+            DecodeError::MissingField(field) => write!(f, "missing {}", field),
+            DecodeError::InvalidField(field) => write!(f, "invalid {}", field),
+        }
+    }
 }
 
-#[allow(unused_variables)]
-fn invalid_packet_parse(
-    buffer: Vec<u8>,
-    len: usize,
-) -> Result<HashMap<&'static str, String>, std::io::Error> {
-    panic!(
-        "You're attempting to parse a packet that is not valid for a server to send to a client"
-    );
+impl std::error::Error for DecodeError {}
+
+impl From<DecodeError> for std::io::Error {
+    fn from(e: DecodeError) -> Self {
+        std::io::Error::new(std::io::ErrorKind::InvalidData, e)
+    }
 }
 
-/// A Packet contains an identifier of the packet type and the functions responsible for creating a
-/// packet (create) and for parsing one (parse).
-pub struct Packet {
-    /// Designation of the actual packet type.
-    pub packet_type: char,
-    /// Parser for a given function, the returned HashMap contains at least one field (`type`)
-    /// which is set to the `packet_type`.
-    pub parse: fn(Vec<u8>, usize) -> Result<HashMap<&'static str, String>, std::io::Error>,
-    /// Used to create a valid packet with all the provided fields.
-    pub create: fn(Vec<&str>) -> Vec<u8>,
+/// Generic packet creator: joins the fields with the ^A field separator and
+/// prepends the packet size and type.
+fn packet_create(packet_type: char, fields: &[&str]) -> Result<Vec<u8>, EncodeError> {
+    packet_create_with(packet_type, fields, Encoding::Utf8)
 }
 
-/// These are all the valid packet types we know of.
-pub static PACKETS: [&Packet; 7] = [
-    &LOGIN, &PROTOCOL, &STATUS, &OPEN, &PERSONAL, &COMMAND, &BEEP,
-];
+/// Like `packet_create()`, with the fields in the given encoding.
+fn packet_create_with(
+    packet_type: char,
+    fields: &[&str],
+    encoding: Encoding,
+) -> Result<Vec<u8>, EncodeError> {
+    if fields.iter().any(|f| f.contains(&['\x00', '\x01'][..])) {
+        return Err(EncodeError::InvalidCharacter);
+    }
 
-/// Convenience method for making InvalidData Errors
-fn err(msg: &str) -> Error {
-    Error::new(ErrorKind::InvalidData, msg)
+    let text = fields.join("\x01");
+    let data = encoding.encode(&text);
+    if data.len() > MAX_DATA_LEN {
+        return Err(EncodeError::TooLong(data.len()));
+    }
+
+    // Account for the packet type and NUL byte
+    let dlen = data.len() + 2;
+
+    let mut v = Vec::<u8>::with_capacity(dlen + 1);
+    v.push(u8::try_from(dlen).unwrap());
+    v.push(packet_type as u8);
+    v.extend_from_slice(&data);
+    v.push(0x00);
+
+    trace!(packet_type = %packet_type, len = v.len(), "created packet");
+
+    Ok(v)
 }
 
-/// Login packet, used to join the initial channel after connecting
-pub static LOGIN: Packet = Packet {
-    packet_type: T_LOGIN,
-    parse: login_packet_parse,
-    create: login_packet_create,
-};
-
-fn login_packet_parse(
-    buffer: Vec<u8>,
-    _len: usize,
-) -> Result<HashMap<&'static str, String>, std::io::Error> {
-    // A received login packet should only contain the packet type byte terminated
-    // by a NUL.
-    if buffer[1] != b'\x00' {
-        Err(err("Invalid login packet"))
-    } else {
-        Ok(hashmap! { "type" => T_LOGIN.to_string() })
+/// Split `text` into parts of at most `max` bytes, preferably at whitespace and
+/// otherwise at a character boundary. `max` must be at least 4 bytes, which fits
+/// any UTF-8 character.
+pub(crate) fn split_text(text: &str, max: usize) -> Vec<&str> {
+    let mut parts = vec![];
+    let mut rest = text;
+
+    while rest.len() > max {
+        let mut end = max;
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+
+        match rest[..end].rfind(char::is_whitespace).filter(|i| *i > 0) {
+            Some(i) => {
+                parts.push(rest[..i].trim_end());
+                rest = rest[i..].trim_start();
+            }
+            None => {
+                parts.push(&rest[..end]);
+                rest = &rest[end..];
+            }
+        }
     }
-}
 
-fn login_packet_create(fields: Vec<&str>) -> Vec<u8> {
-    packet_create(T_LOGIN, fields)
+    parts.push(rest);
+    parts.retain(|p| !p.is_empty());
+    if parts.is_empty() {
+        parts.push("");
+    }
+    parts
 }
 
-/// Protocol packet
-pub static PROTOCOL: Packet = Packet {
-    packet_type: T_PROTOCOL,
-    parse: protocol_packet_parse,
-    create: protocol_packet_create,
-};
-
-/// Create an iterator over the packet buffer's fields
-fn packet_buffer_iter(buffer: &[u8], len: usize) -> impl Iterator<Item = Cow<str>> {
-    // Create a copy of the message to split at the ^A field separator,
-    // note it removes the first byte (packet_type) and the last byte (NUL).
-    let message = &buffer[1..len - 1];
-
-    // Split the packet on ^A (Start Of Heading), or ASCII 0x1
-    message
-        .split(|sep| *sep == 0x1)
-        .map(|e| String::from_utf8_lossy(e))
+/// The fields of a received packet, split on the ^A (Start Of Heading) separator.
+struct Fields(std::vec::IntoIter<String>);
+
+impl Fields {
+    /// Split `data`, which is the packet without its size and type, and decode the
+    /// fields.
+    fn new(data: &[u8], encoding: Encoding) -> Fields {
+        // Strip the trailing NUL byte, if any.
+        let data = match data.split_last() {
+            Some((0, rest)) => rest,
+            _ => data,
+        };
+
+        let fields: Vec<String> = data
+            .split(|sep| *sep == 0x1)
+            .map(|e| encoding.decode(e))
+            .collect();
+
+        Fields(fields.into_iter())
+    }
+
+    fn required(&mut self, name: &'static str) -> Result<String, DecodeError> {
+        self.0.next().ok_or(DecodeError::MissingField(name))
+    }
+
+    fn optional(&mut self) -> Option<String> {
+        self.0.next().filter(|f| !f.is_empty())
+    }
+
+    fn number<T: std::str::FromStr>(&mut self, name: &'static str) -> Result<T, DecodeError> {
+        self.required(name)?
+            .trim()
+            .parse()
+            .map_err(|_| DecodeError::InvalidField(name))
+    }
 }
 
-fn protocol_packet_parse(
-    buffer: Vec<u8>,
-    len: usize,
-) -> Result<HashMap<&'static str, String>, std::io::Error> {
-    let mut iter = packet_buffer_iter(&buffer, len);
-
-    // Skip the first field (protocol level)
-    let _ = iter.next();
-    let hostid = iter.next().ok_or_else(|| err("Missing hostid"))?;
-    let clientid = iter.next().ok_or_else(|| err("Missing clientid"))?;
-
-    Ok(hashmap! {
-        "type" => T_PROTOCOL.to_string(),
-        "hostid" => hostid.to_string(),
-        "clientid" => clientid.to_string(),
-    })
+/// Split a received packet (without the size byte) into its type and its fields.
+fn packet_split(buffer: &[u8], encoding: Encoding) -> Result<(char, Fields), DecodeError> {
+    match buffer.split_first() {
+        Some((t, data)) => Ok((*t as char, Fields::new(data, encoding))),
+        None => Err(DecodeError::Empty),
+    }
 }
 
-fn protocol_packet_create(fields: Vec<&str>) -> Vec<u8> {
-    packet_create(T_PROTOCOL, fields)
+/// Packets sent by a client to the server.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ClientPacket {
+    /// Login packet, used to join the initial group after connecting.
+    Login {
+        login_id: String,
+        nickname: String,
+        group: String,
+        /// Either "login" to join the group, or "w" to only list users.
+        command: String,
+        password: Option<String>,
+    },
+    /// Message to the current group.
+    Open(String),
+    /// Command, such as a personal message or changing the group.
+    Command { command: String, args: String },
+    /// Ping with an optional identifier, which is echoed back in the pong.
+    Ping(String),
+    /// Reply to a ping, with the identifier of the ping.
+    Pong(String),
+    /// No-op, e.g. to keep an idle connection alive.
+    NoOp,
 }
 
-/// Status packet
-pub static STATUS: Packet = Packet {
-    packet_type: T_STATUS,
-    parse: status_packet_parse,
-    create: invalid_packet_create,
-};
-
-fn status_packet_parse(
-    buffer: Vec<u8>,
-    len: usize,
-) -> Result<HashMap<&'static str, String>, std::io::Error> {
-    let mut iter = packet_buffer_iter(&buffer, len);
-
-    let category = iter.next().ok_or_else(|| err("Missing category"))?;
-    let message = iter.next().ok_or_else(|| err("Missing message"))?;
-
-    Ok(hashmap! {
-        "type" => T_STATUS.to_string(),
-        "category" => category.to_string(),
-        "message" => message.to_string(),
-    })
+impl ClientPacket {
+    /// Convenience method for creating a command packet.
+    pub fn command(command: &str, args: &str) -> ClientPacket {
+        ClientPacket::Command {
+            command: command.to_string(),
+            args: args.to_string(),
+        }
+    }
+
+    /// Create the open packets for sending `text` to the group, splitting it over
+    /// multiple packets at word boundaries if it's too long for a single one.
+    pub fn open(text: &str) -> Vec<ClientPacket> {
+        split_text(text, MAX_DATA_LEN)
+            .into_iter()
+            .map(|part| ClientPacket::Open(part.to_string()))
+            .collect()
+    }
+
+    /// Create the command packets for sending `text` as a personal message to `recipient`,
+    /// splitting it over multiple packets at word boundaries if it's too long for a single one.
+    pub fn personal(recipient: &str, text: &str) -> Result<Vec<ClientPacket>, EncodeError> {
+        // Account for the command, field separator and the space after the recipient.
+        let overhead = CMD_MSG.len() + 1 + recipient.len() + 1;
+        if overhead + 4 > MAX_DATA_LEN {
+            return Err(EncodeError::TooLong(overhead));
+        }
+
+        Ok(split_text(text, MAX_DATA_LEN - overhead)
+            .into_iter()
+            .map(|part| ClientPacket::command(CMD_MSG, &format!("{} {}", recipient, part)))
+            .collect())
+    }
+
+    /// The packet type (`T_*`) of this packet.
+    pub fn packet_type(&self) -> char {
+        match self {
+            ClientPacket::Login { .. } => T_LOGIN,
+            ClientPacket::Open(_) => T_OPEN,
+            ClientPacket::Command { .. } => T_COMMAND,
+            ClientPacket::Ping(_) => T_PING,
+            ClientPacket::Pong(_) => T_PONG,
+            ClientPacket::NoOp => T_NOOP,
+        }
+    }
+
+    /// Encode the packet, including the leading packet size, ready to be sent.
+    pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        self.encode_with(Encoding::Utf8)
+    }
+
+    /// Encode the packet like `encode()`, with the text in the given encoding.
+    pub fn encode_with(&self, encoding: Encoding) -> Result<Vec<u8>, EncodeError> {
+        if let ClientPacket::Command { command, .. } = self {
+            if !SUPPORTED_COMMANDS.contains(&command.as_str()) {
+                return Err(EncodeError::UnsupportedCommand(command.clone()));
+            }
+        }
+        self.encode_unchecked_with(encoding)
+    }
+
+    /// Encode the packet like `encode()`, but allow command packets with any command
+    /// rather than just the ones this library supports.
+    pub fn encode_unchecked(&self) -> Result<Vec<u8>, EncodeError> {
+        self.encode_unchecked_with(Encoding::Utf8)
+    }
+
+    /// Encode the packet like `encode_unchecked()`, with the text in the given encoding.
+    pub fn encode_unchecked_with(&self, encoding: Encoding) -> Result<Vec<u8>, EncodeError> {
+        let create =
+            |packet_type, fields: &[&str]| packet_create_with(packet_type, fields, encoding);
+
+        match self {
+            ClientPacket::Login {
+                login_id,
+                nickname,
+                group,
+                command,
+                password,
+            } => {
+                let mut fields = vec![login_id.as_str(), nickname, group, command];
+                if let Some(password) = password {
+                    fields.push(password);
+                }
+                create(T_LOGIN, &fields)
+            }
+            ClientPacket::Open(message) => create(T_OPEN, &[message]),
+            ClientPacket::Command { command, args } => create(T_COMMAND, &[command, args]),
+            ClientPacket::Ping(id) => create(T_PING, &[id]),
+            ClientPacket::Pong(id) => create(T_PONG, &[id]),
+            ClientPacket::NoOp => create(T_NOOP, &[]),
+        }
+    }
+
+    /// Decode a packet received from a client. `buffer` contains the packet
+    /// without the leading packet size.
+    pub fn decode(buffer: &[u8]) -> Result<ClientPacket, DecodeError> {
+        let (packet_type, mut fields) = packet_split(buffer, Encoding::Utf8)?;
+
+        let packet = match packet_type {
+            T_LOGIN => ClientPacket::Login {
+                login_id: fields.required("login id")?,
+                nickname: fields.required("nickname")?,
+                group: fields.required("group")?,
+                command: fields.required("login command")?,
+                password: fields.optional(),
+            },
+            T_OPEN => ClientPacket::Open(fields.required("message")?),
+            T_COMMAND => ClientPacket::Command {
+                command: fields.required("command")?,
+                args: fields.optional().unwrap_or_default(),
+            },
+            T_PING => ClientPacket::Ping(fields.optional().unwrap_or_default()),
+            T_PONG => ClientPacket::Pong(fields.optional().unwrap_or_default()),
+            T_NOOP => ClientPacket::NoOp,
+            t => return Err(DecodeError::UnknownType(t)),
+        };
+
+        Ok(packet)
+    }
 }
 
-/// Open packet (normal chats)
-pub static OPEN: Packet = Packet {
-    packet_type: T_OPEN,
-    parse: open_packet_parse,
-    create: open_packet_create,
-};
-
-fn open_packet_parse(
-    buffer: Vec<u8>,
-    len: usize,
-) -> Result<HashMap<&'static str, String>, std::io::Error> {
-    let mut iter = packet_buffer_iter(&buffer, len);
-
-    let nickname = iter.next().ok_or_else(|| err("Missing nickname"))?;
-    let message = iter.next().ok_or_else(|| err("Missing message"))?;
-
-    Ok(hashmap! {
-        "type" => T_OPEN.to_string(),
-        "nickname" => nickname.to_string(),
-        "message" => message.to_string(),
-    })
+/// Output sent by the server in response to a command.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommandOutput {
+    /// Generic output (`co`).
+    Output(String),
+    /// End of the output for a command (`ec`), optionally naming the command.
+    End(Option<String>),
+    /// Header of a who-listing (`wh`).
+    WhoHeader,
+    /// A user in a who-listing (`wl`).
+    WhoLine(UserInfo),
+    /// Header for a group in a who-listing (`gh`).
+    GroupHeader { name: String, topic: Option<String> },
+    /// Command header (`ch`).
+    CommandHeader,
 }
 
-fn open_packet_create(fields: Vec<&str>) -> Vec<u8> {
-    packet_create(T_OPEN, fields)
+impl CommandOutput {
+    fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            CommandOutput::Output(message) => packet_create(T_CMDOUT, &[CMDOUT_CO, message]),
+            CommandOutput::End(None) => packet_create(T_CMDOUT, &[CMDOUT_EC]),
+            CommandOutput::End(Some(command)) => packet_create(T_CMDOUT, &[CMDOUT_EC, command]),
+            CommandOutput::WhoHeader => packet_create(T_CMDOUT, &[CMDOUT_WH]),
+            CommandOutput::WhoLine(user) => packet_create(
+                T_CMDOUT,
+                &[
+                    CMDOUT_WL,
+                    if user.moderator { "m" } else { " " },
+                    &user.nickname,
+                    &user.idle.to_string(),
+                    // Response time, which is unused.
+                    "0",
+                    &user.login_time.to_string(),
+                    &user.username,
+                    &user.hostname,
+                    if user.registered { " " } else { "(nr)" },
+                ],
+            ),
+            CommandOutput::GroupHeader { name, topic } => packet_create(
+                T_CMDOUT,
+                &[CMDOUT_GH, name, topic.as_deref().unwrap_or_default()],
+            ),
+            CommandOutput::CommandHeader => packet_create(T_CMDOUT, &[CMDOUT_CH]),
+        }
+    }
+
+    fn decode(fields: &mut Fields) -> Result<CommandOutput, DecodeError> {
+        let output = match fields.required("output type")?.as_str() {
+            CMDOUT_CO => CommandOutput::Output(fields.required("message")?),
+            CMDOUT_EC => CommandOutput::End(fields.optional()),
+            CMDOUT_WL => {
+                // The moderator field is 'm' (or '*' on some servers) for moderators,
+                // a space otherwise.
+                let moderator = fields.required("moderator flag")?;
+                let nickname = fields.required("nickname")?;
+                let idle = fields.number("idle time")?;
+                // Response time is unused by servers and always 0.
+                let _ = fields.required("response time")?;
+                let login_time = fields.number("login time")?;
+                let username = fields.required("username")?;
+                let hostname = fields.required("hostname")?;
+                // "(nr)" is sent for nicknames which aren't registered.
+                let registration = fields.optional().unwrap_or_default();
+
+                CommandOutput::WhoLine(UserInfo {
+                    nickname,
+                    moderator: matches!(moderator.trim(), "m" | "*"),
+                    idle,
+                    login_time,
+                    username,
+                    hostname,
+                    registered: registration.trim() != "(nr)",
+                })
+            }
+            CMDOUT_WH => CommandOutput::WhoHeader,
+            CMDOUT_GH => CommandOutput::GroupHeader {
+                name: fields.required("group")?,
+                topic: fields.optional(),
+            },
+            CMDOUT_CH => CommandOutput::CommandHeader,
+            _ => return Err(DecodeError::InvalidField("output type")),
+        };
+
+        Ok(output)
+    }
 }
 
-/// Personal message packet (direct chats)
-pub static PERSONAL: Packet = Packet {
-    packet_type: T_PERSONAL,
-    parse: personal_packet_parse,
-    create: invalid_packet_create,
-};
-
-fn personal_packet_parse(
-    buffer: Vec<u8>,
-    len: usize,
-) -> Result<HashMap<&'static str, String>, std::io::Error> {
-    let mut iter = packet_buffer_iter(&buffer, len);
-
-    let nickname = iter.next().ok_or_else(|| err("Missing nickname"))?;
-    let message = iter.next().ok_or_else(|| err("Missing message"))?;
-
-    Ok(hashmap! {
-        "type" => T_PERSONAL.to_string(),
-        "nickname" => nickname.to_string(),
-        "message" => message.to_string(),
-    })
+/// Packets sent by the server to a client.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ServerPacket {
+    /// Empty packet confirming a successful login.
+    Login,
+    /// Message sent to the group.
+    Open { nickname: String, message: String },
+    /// Personal message from another user.
+    Personal { nickname: String, message: String },
+    /// Status message, such as users arriving or departing.
+    Status {
+        category: StatusCategory,
+        message: String,
+    },
+    /// Error, sent when a command or message was rejected.
+    Error(String),
+    /// Important message, such as an announcement that the server is shutting down.
+    Important {
+        category: StatusCategory,
+        message: String,
+    },
+    /// The server is ending the session.
+    Exit,
+    /// Output in response to a command such as `w` or `topic`.
+    CommandOutput(CommandOutput),
+    /// Protocol information, sent upon connecting.
+    Protocol {
+        level: String,
+        host_id: String,
+        server_id: String,
+    },
+    /// Another user beeped the client.
+    Beep(String),
+    /// Ping with an optional identifier, which must be echoed back in a pong.
+    Ping(String),
+    /// Reply to a ping sent by the client.
+    Pong(String),
+    /// No-op.
+    NoOp,
 }
 
-/// Command packet
-pub static COMMAND: Packet = Packet {
-    packet_type: T_COMMAND,
-    parse: invalid_packet_parse,
-    create: command_packet_create,
-};
+impl ServerPacket {
+    /// The packet type (`T_*`) of this packet.
+    pub fn packet_type(&self) -> char {
+        match self {
+            ServerPacket::Login => T_LOGIN,
+            ServerPacket::Open { .. } => T_OPEN,
+            ServerPacket::Personal { .. } => T_PERSONAL,
+            ServerPacket::Status { .. } => T_STATUS,
+            ServerPacket::Error(_) => T_ERROR,
+            ServerPacket::Important { .. } => T_IMPORTANT,
+            ServerPacket::Exit => T_EXIT,
+            ServerPacket::CommandOutput(_) => T_CMDOUT,
+            ServerPacket::Protocol { .. } => T_PROTOCOL,
+            ServerPacket::Beep(_) => T_BEEP,
+            ServerPacket::Ping(_) => T_PING,
+            ServerPacket::Pong(_) => T_PONG,
+            ServerPacket::NoOp => T_NOOP,
+        }
+    }
 
-#[allow(unused_variables)]
-/// Create a new command packet. Based on the icbd server implementation the following
-/// commands can be issued:
-///   "?"      -- help
-///   "beep"   -- beep
-///   "boot"   -- boot
-///   "g"      -- change group
-///   "m"      -- personal message
-///   "msg"    -- personal message
-///   "name"   -- change name
-///   "nobeep" -- disable beep
-///   "pass"   -- pass moderator
-///   "topic"  -- set topic
-///   "w"      -- list users
-pub const CMD_HELP: &str = "?";
-pub const CMD_BEEP: &str = "beep";
-pub const CMD_BOOT: &str = "boot";
-pub const CMD_G: &str = "g";
-pub const CMD_M: &str = "m";
-pub const CMD_MSG: &str = "msg";
-pub const CMD_NAME: &str = "name";
-pub const CMD_NOBEEP: &str = "nobeep";
-pub const CMD_PASS: &str = "pass";
-pub const CMD_TOPIC: &str = "topic";
-pub const CMD_W: &str = "w";
+    /// Encode the packet, including the leading packet size, ready to be sent.
+    pub fn encode(&self) -> Result<Vec<u8>, EncodeError> {
+        match self {
+            ServerPacket::Login => packet_create(T_LOGIN, &[]),
+            ServerPacket::Open { nickname, message } => packet_create(T_OPEN, &[nickname, message]),
+            ServerPacket::Personal { nickname, message } => {
+                packet_create(T_PERSONAL, &[nickname, message])
+            }
+            ServerPacket::Status { category, message } => {
+                packet_create(T_STATUS, &[category.as_str(), message])
+            }
+            ServerPacket::Error(message) => packet_create(T_ERROR, &[message]),
+            ServerPacket::Important { category, message } => {
+                packet_create(T_IMPORTANT, &[category.as_str(), message])
+            }
+            ServerPacket::Exit => packet_create(T_EXIT, &[]),
+            ServerPacket::CommandOutput(output) => output.encode(),
+            ServerPacket::Protocol {
+                level,
+                host_id,
+                server_id,
+            } => packet_create(T_PROTOCOL, &[level, host_id, server_id]),
+            ServerPacket::Beep(nickname) => packet_create(T_BEEP, &[nickname]),
+            ServerPacket::Ping(id) => packet_create(T_PING, &[id]),
+            ServerPacket::Pong(id) => packet_create(T_PONG, &[id]),
+            ServerPacket::NoOp => packet_create(T_NOOP, &[]),
+        }
+    }
 
-fn command_packet_create(fields: Vec<&str>) -> Vec<u8> {
-    let all_cmds = vec![CMD_BEEP, CMD_M, CMD_MSG, CMD_NAME];
-    let cmd = fields[0];
+    /// Decode a packet received from the server. `buffer` contains the packet
+    /// without the leading packet size.
+    pub fn decode(buffer: &[u8]) -> Result<ServerPacket, DecodeError> {
+        ServerPacket::decode_with(buffer, Encoding::Utf8)
+    }
 
-    if all_cmds.contains(&cmd) {
-        packet_create(T_COMMAND, fields)
-    } else {
-        panic!("Command {} not support (yet)!", cmd);
+    /// Decode a packet like `decode()`, with the text in the given encoding.
+    pub fn decode_with(buffer: &[u8], encoding: Encoding) -> Result<ServerPacket, DecodeError> {
+        let (packet_type, mut fields) = packet_split(buffer, encoding)?;
+
+        let packet = match packet_type {
+            T_LOGIN => {
+                // A received login packet should only contain the packet type byte.
+                if fields.optional().is_some() {
+                    return Err(DecodeError::InvalidField("login packet"));
+                }
+                ServerPacket::Login
+            }
+            T_OPEN => ServerPacket::Open {
+                nickname: fields.required("nickname")?,
+                message: fields.required("message")?,
+            },
+            T_PERSONAL => ServerPacket::Personal {
+                nickname: fields.required("nickname")?,
+                message: fields.required("message")?,
+            },
+            T_STATUS => ServerPacket::Status {
+                category: StatusCategory::from(fields.required("category")?.as_str()),
+                message: fields.required("message")?,
+            },
+            T_ERROR => ServerPacket::Error(fields.required("message")?),
+            T_IMPORTANT => ServerPacket::Important {
+                category: StatusCategory::from(fields.required("category")?.as_str()),
+                message: fields.required("message")?,
+            },
+            T_EXIT => ServerPacket::Exit,
+            T_CMDOUT => ServerPacket::CommandOutput(CommandOutput::decode(&mut fields)?),
+            T_PROTOCOL => ServerPacket::Protocol {
+                level: fields.required("protocol level")?,
+                host_id: fields.required("hostid")?,
+                server_id: fields.required("serverid")?,
+            },
+            T_BEEP => ServerPacket::Beep(fields.required("nickname")?),
+            T_PING => ServerPacket::Ping(fields.optional().unwrap_or_default()),
+            T_PONG => ServerPacket::Pong(fields.optional().unwrap_or_default()),
+            T_NOOP => ServerPacket::NoOp,
+            t => return Err(DecodeError::UnknownType(t)),
+        };
+
+        Ok(packet)
     }
 }
 
-/// Beep beep
-pub static BEEP: Packet = Packet {
-    packet_type: T_BEEP,
-    parse: beep_packet_parse,
-    create: invalid_packet_create,
-};
-
-fn beep_packet_parse(
-    buffer: Vec<u8>,
-    len: usize,
-) -> Result<HashMap<&'static str, String>, std::io::Error> {
-    let mut iter = packet_buffer_iter(&buffer, len);
-
-    let nickname = iter.next().ok_or_else(|| err("Missing nickname"))?;
-
-    Ok(hashmap! {
-        "type" => T_BEEP.to_string(),
-        "nickname" => nickname.to_string(),
-    })
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_text_is_not_split() {
+        assert_eq!(split_text("hello world", MAX_DATA_LEN), vec!["hello world"]);
+        assert_eq!(split_text("", MAX_DATA_LEN), vec![""]);
+    }
+
+    #[test]
+    fn split_prefers_whitespace() {
+        let text = format!("{} {}", "a".repeat(200), "b".repeat(100));
+        assert_eq!(
+            split_text(&text, MAX_DATA_LEN),
+            vec!["a".repeat(200), "b".repeat(100)]
+        );
+    }
+
+    #[test]
+    fn split_without_whitespace() {
+        let text = "x".repeat(600);
+        let parts = split_text(&text, MAX_DATA_LEN);
+        assert_eq!(
+            parts.iter().map(|p| p.len()).collect::<Vec<_>>(),
+            vec![253, 253, 94]
+        );
+        assert_eq!(parts.concat(), text);
+    }
+
+    #[test]
+    fn split_at_character_boundary() {
+        // 'é' takes two bytes, so the 253 byte boundary falls within a character.
+        let text = "é".repeat(200);
+        let parts = split_text(&text, MAX_DATA_LEN);
+        assert_eq!(
+            parts.iter().map(|p| p.len()).collect::<Vec<_>>(),
+            vec![252, 148]
+        );
+        assert_eq!(parts.concat(), text);
+
+        let text = format!("a{}", "€".repeat(100));
+        for part in split_text(&text, MAX_DATA_LEN) {
+            assert!(part.len() <= MAX_DATA_LEN);
+        }
+    }
+
+    #[test]
+    fn split_ignores_leading_whitespace() {
+        // Whitespace at the very start isn't used to split, as that would produce an
+        // empty part.
+        let text = format!(" {}", "x".repeat(300));
+        let parts = split_text(&text, MAX_DATA_LEN);
+        assert_eq!(parts.len(), 2);
+        assert!(parts
+            .iter()
+            .all(|p| !p.is_empty() && p.len() <= MAX_DATA_LEN));
+        assert_eq!(parts.concat(), text);
+    }
+
+    #[test]
+    fn open_packets_fit() {
+        let text = "word ".repeat(200);
+        let packets = ClientPacket::open(&text);
+        assert!(packets.len() > 1);
+        for packet in packets {
+            let data = packet.encode().unwrap();
+            assert!(data.len() <= MAX_DATA_LEN + 3);
+        }
+    }
+
+    #[test]
+    fn personal_accounts_for_recipient() {
+        let recipient = "r".repeat(100);
+        let text = "x".repeat(300);
+        let packets = ClientPacket::personal(&recipient, &text).unwrap();
+
+        let mut sent = String::new();
+        for packet in &packets {
+            assert!(packet.encode().unwrap().len() <= MAX_DATA_LEN + 3);
+            match packet {
+                ClientPacket::Command { command, args } => {
+                    assert_eq!(command, CMD_MSG);
+                    let (to, part) = args.split_once(' ').unwrap();
+                    assert_eq!(to, recipient);
+                    sent.push_str(part);
+                }
+                packet => panic!("unexpected packet {:?}", packet),
+            }
+        }
+        assert_eq!(sent, text);
+    }
+
+    #[test]
+    fn personal_with_too_long_recipient() {
+        let recipient = "r".repeat(250);
+        assert!(matches!(
+            ClientPacket::personal(&recipient, "hi"),
+            Err(EncodeError::TooLong(_))
+        ));
+    }
+
+    #[test]
+    fn login_is_not_truncated() {
+        let login = ClientPacket::Login {
+            login_id: "l".repeat(100),
+            nickname: "n".repeat(100),
+            group: "g".repeat(100),
+            command: LOGIN_LOGIN.to_string(),
+            password: None,
+        };
+        assert!(matches!(login.encode(), Err(EncodeError::TooLong(_))));
+    }
+
+    #[test]
+    fn separator_in_field() {
+        assert_eq!(
+            ClientPacket::Open("a\x01b".to_string()).encode(),
+            Err(EncodeError::InvalidCharacter)
+        );
+    }
 }
@@ -0,0 +1,878 @@
+//! A small ICB server, available with the `server` feature.
+//!
+//! It implements enough of the protocol to run clients and bots against during
+//! development: logging in, groups, open and personal messages, beeps, who-listings,
+//! topics and moderators, who can pass moderation on and boot users. Nicknames can't
+//! be registered and groups have no modes other than their moderator.
+
+use crossbeam_channel::{bounded, unbounded, Receiver, Sender, TrySendError};
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::thread;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+use crate::codec::{ClientReader, DEFAULT_MAX_PACKET_LEN};
+use crate::packets::{self, ClientPacket, CommandOutput, ServerPacket, MAX_DATA_LEN};
+use crate::{NoBeepMode, StatusCategory, UserInfo};
+
+/// Maximum length of a nickname, as enforced by icbd.
+pub const MAX_NICKNAME_LEN: usize = 12;
+
+/// Maximum length of a group topic; longer topics are refused, like icbd does.
+pub const MAX_TOPIC_LEN: usize = 160;
+
+/// Group that booted users are moved to.
+pub const BOOT_GROUP: &str = "~IDLE~";
+
+/// Number of packets queued for a client before it's considered stuck and disconnected.
+pub const MAX_QUEUED_PACKETS: usize = 1024;
+
+/// Settings for an `IcbServer`.
+#[derive(Clone, Debug)]
+pub struct ServerConfig {
+    /// Address to listen on.
+    pub address: String,
+    pub port: u16,
+    /// Name of the host, as sent to clients in the protocol packet.
+    pub host_id: String,
+    /// Description of the server, as sent to clients in the protocol packet.
+    pub server_id: String,
+    /// Group to join when a client logs in without one.
+    pub default_group: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> ServerConfig {
+        ServerConfig {
+            address: "127.0.0.1".to_string(),
+            port: 7326,
+            host_id: "localhost".to_string(),
+            server_id: format!("icb-rs {}", env!("CARGO_PKG_VERSION")),
+            default_group: "1".to_string(),
+        }
+    }
+}
+
+/// An ICB server which accepts clients on a TCP port.
+///
+/// Every client is read from and written to by threads of its own, while a single thread
+/// keeps track of the users and groups. A client that doesn't keep up with the packets
+/// sent to it is disconnected, so it can't hold up the others.
+#[derive(Debug)]
+pub struct IcbServer {
+    listener: TcpListener,
+    config: ServerConfig,
+}
+
+impl IcbServer {
+    /// Start listening on the configured address and port.
+    pub fn bind(config: ServerConfig) -> io::Result<IcbServer> {
+        let listener = TcpListener::bind((config.address.as_str(), config.port))?;
+        Ok(IcbServer { listener, config })
+    }
+
+    /// The address the server is listening on, e.g. to find out which port was picked
+    /// when binding to port 0.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve clients, which blocks for as long as the server is running.
+    pub fn run(self) {
+        let (input_s, input_r) = unbounded();
+
+        let listener = self.listener;
+        thread::spawn(move || accept(listener, input_s));
+
+        let mut hub = Hub::new(self.config);
+        for input in input_r {
+            hub.handle(input);
+        }
+    }
+}
+
+/// Identifies a connection for as long as the server runs.
+type ConnId = u64;
+
+/// What happened on one of the connections.
+enum Input {
+    Connected {
+        id: ConnId,
+        sock: TcpStream,
+        queue: Sender<Output>,
+        hostname: String,
+    },
+    Packet(ConnId, ClientPacket),
+    Closed(ConnId),
+}
+
+/// What to do on one of the connections, in order.
+enum Output {
+    Send(Vec<u8>),
+    /// Close the connection after everything queued before has been sent.
+    Close,
+}
+
+/// Accept new connections, starting a reader and a writer thread for each of them.
+fn accept(listener: TcpListener, input_s: Sender<Input>) {
+    for (id, sock) in (0..).zip(listener.incoming()) {
+        // Failing to accept one client doesn't keep us from accepting the next.
+        let sock = match sock {
+            Ok(sock) => sock,
+            Err(_) => continue,
+        };
+        // Chat messages are small, don't hold them back waiting for more data.
+        sock.set_nodelay(true).ok();
+        let (reader, writer) = match (sock.try_clone(), sock.try_clone()) {
+            (Ok(reader), Ok(writer)) => (reader, writer),
+            _ => continue,
+        };
+        let hostname = sock
+            .peer_addr()
+            .map(|addr| addr.ip().to_string())
+            .unwrap_or_default();
+
+        let (queue, queue_r) = bounded(MAX_QUEUED_PACKETS);
+        thread::spawn(move || write(writer, queue_r));
+
+        if input_s
+            .send(Input::Connected {
+                id,
+                sock,
+                queue,
+                hostname,
+            })
+            .is_err()
+        {
+            return;
+        }
+
+        let input_s = input_s.clone();
+        thread::spawn(move || {
+            let mut reader = ClientReader::new(reader, DEFAULT_MAX_PACKET_LEN);
+            loop {
+                match reader.read() {
+                    Ok(packet) => {
+                        if input_s.send(Input::Packet(id, packet)).is_err() {
+                            return;
+                        }
+                    }
+                    // An invalid packet is skipped, the connection itself is still usable.
+                    Err(ref e) if e.kind() == io::ErrorKind::InvalidData => continue,
+                    Err(_) => break,
+                }
+            }
+            input_s.send(Input::Closed(id)).ok();
+        });
+    }
+}
+
+/// Send the packets queued for a client until the connection is closed, or the
+/// hub forgets about it.
+fn write(mut sock: TcpStream, queue_r: Receiver<Output>) {
+    for output in queue_r {
+        match output {
+            Output::Send(data) => {
+                if sock.write_all(&data).is_err() {
+                    break;
+                }
+            }
+            Output::Close => break,
+        }
+    }
+    // The reader thread notices and reports the connection as closed.
+    sock.shutdown(Shutdown::Both).ok();
+}
+
+/// A client that has connected.
+struct Conn {
+    /// Used to disconnect the client right away; writing happens through `queue`.
+    sock: TcpStream,
+    queue: Sender<Output>,
+    hostname: String,
+    /// Set once the client has logged in.
+    user: Option<User>,
+}
+
+struct User {
+    nickname: String,
+    /// Account name as sent in the login packet.
+    login_id: String,
+    group: String,
+    /// Time of the login, in seconds since the epoch.
+    login_time: i64,
+    /// Time the user last sent a message or command.
+    last_active: Instant,
+    nobeep: NoBeepMode,
+}
+
+struct Group {
+    topic: Option<String>,
+    moderator: Option<ConnId>,
+}
+
+/// State of the server: the connections and the groups their users are in.
+struct Hub {
+    config: ServerConfig,
+    conns: HashMap<ConnId, Conn>,
+    groups: HashMap<String, Group>,
+}
+
+impl Hub {
+    fn new(config: ServerConfig) -> Hub {
+        Hub {
+            config,
+            conns: HashMap::new(),
+            groups: HashMap::new(),
+        }
+    }
+
+    fn handle(&mut self, input: Input) {
+        match input {
+            Input::Connected {
+                id,
+                sock,
+                queue,
+                hostname,
+            } => {
+                self.conns.insert(
+                    id,
+                    Conn {
+                        sock,
+                        queue,
+                        hostname,
+                        user: None,
+                    },
+                );
+                self.send(
+                    id,
+                    ServerPacket::Protocol {
+                        level: "1".to_string(),
+                        host_id: self.config.host_id.clone(),
+                        server_id: self.config.server_id.clone(),
+                    },
+                );
+            }
+            Input::Packet(id, packet) => self.packet(id, packet),
+            Input::Closed(id) => {
+                if self.user(id).is_some() {
+                    let message = format!("{} has signed off.", self.ident(id));
+                    self.leave(id, StatusCategory::SignOff, &message);
+                }
+                self.conns.remove(&id);
+            }
+        }
+    }
+
+    fn user(&self, id: ConnId) -> Option<&User> {
+        self.conns.get(&id).and_then(|conn| conn.user.as_ref())
+    }
+
+    fn user_mut(&mut self, id: ConnId) -> Option<&mut User> {
+        self.conns.get_mut(&id).and_then(|conn| conn.user.as_mut())
+    }
+
+    fn nickname(&self, id: ConnId) -> String {
+        self.user(id)
+            .map(|user| user.nickname.clone())
+            .unwrap_or_default()
+    }
+
+    /// The user as introduced to others, e.g. "jasper (jasper@192.0.2.1)".
+    fn ident(&self, id: ConnId) -> String {
+        match self.conns.get(&id) {
+            Some(Conn {
+                hostname,
+                user: Some(user),
+                ..
+            }) => format!("{} ({}@{})", user.nickname, user.login_id, hostname),
+            _ => String::new(),
+        }
+    }
+
+    /// Look up a logged in user by nickname, which is case-insensitive.
+    fn find(&self, nickname: &str) -> Option<ConnId> {
+        self.conns.iter().find_map(|(id, conn)| match &conn.user {
+            Some(user) if user.nickname.eq_ignore_ascii_case(nickname) => Some(*id),
+            _ => None,
+        })
+    }
+
+    /// The users in `group`, in the order they connected.
+    fn members(&self, group: &str) -> Vec<ConnId> {
+        let mut members: Vec<_> = self
+            .conns
+            .iter()
+            .filter(|(_, conn)| conn.user.as_ref().is_some_and(|u| u.group == group))
+            .map(|(id, _)| *id)
+            .collect();
+        members.sort_unstable();
+        members
+    }
+
+    fn is_moderator(&self, id: ConnId) -> bool {
+        self.user(id)
+            .and_then(|user| self.groups.get(&user.group))
+            .is_some_and(|group| group.moderator == Some(id))
+    }
+
+    /// Queue a packet for a client. A client whose queue is full isn't reading what's
+    /// sent to it, so it's disconnected, which the reader thread reports back.
+    fn send(&self, id: ConnId, packet: ServerPacket) {
+        let conn = match self.conns.get(&id) {
+            Some(conn) => conn,
+            None => return,
+        };
+
+        let data = match packet.encode() {
+            Ok(data) => data,
+            // Messages are truncated to fit, so this shouldn't happen; drop the packet
+            // rather than the client.
+            Err(e) => {
+                warn!(conn = id, error = %e, ?packet, "dropping packet");
+                return;
+            }
+        };
+        if let Err(TrySendError::Full(_)) = conn.queue.try_send(Output::Send(data)) {
+            conn.sock.shutdown(Shutdown::Both).ok();
+        }
+    }
+
+    // Messages generated by the server may include user input such as group names and
+    // topics, so they're cut short where needed to fit in a packet.
+
+    fn status(&self, id: ConnId, category: StatusCategory, message: &str) {
+        let message = truncate(message, category.as_str().len() + 1);
+        self.send(
+            id,
+            ServerPacket::Status {
+                category,
+                message: message.to_string(),
+            },
+        );
+    }
+
+    fn error(&self, id: ConnId, message: &str) {
+        self.send(id, ServerPacket::Error(truncate(message, 0).to_string()));
+    }
+
+    fn output(&self, id: ConnId, message: &str) {
+        // The output type "co" and a separator precede the message.
+        let message = truncate(message, 3);
+        self.send(
+            id,
+            ServerPacket::CommandOutput(CommandOutput::Output(message.to_string())),
+        );
+    }
+
+    /// Send a status message to everyone in `group` other than `except`.
+    fn announce(
+        &self,
+        group: &str,
+        except: Option<ConnId>,
+        category: StatusCategory,
+        message: &str,
+    ) {
+        for member in self.members(group) {
+            if Some(member) != except {
+                self.status(member, category.clone(), message);
+            }
+        }
+    }
+
+    /// End the connection once the packets queued for it have been sent, after which
+    /// the reader thread reports it's closed.
+    fn close(&self, id: ConnId) {
+        if let Some(conn) = self.conns.get(&id) {
+            if conn.queue.try_send(Output::Close).is_err() {
+                conn.sock.shutdown(Shutdown::Both).ok();
+            }
+        }
+    }
+
+    fn packet(&mut self, id: ConnId, packet: ClientPacket) {
+        if self.user(id).is_none() {
+            match packet {
+                ClientPacket::Login {
+                    login_id,
+                    nickname,
+                    group,
+                    command,
+                    ..
+                } => self.login(id, login_id, nickname, group, &command),
+                _ => {
+                    self.error(id, "Log in first.");
+                    self.close(id);
+                }
+            }
+            return;
+        }
+
+        if let Some(user) = self.user_mut(id) {
+            user.last_active = Instant::now();
+        }
+
+        match packet {
+            ClientPacket::Login { .. } => self.error(id, "Already logged in."),
+            ClientPacket::Open(message) => self.open(id, &message),
+            ClientPacket::Command { command, args } => self.command(id, &command, args.trim()),
+            ClientPacket::Ping(ping) => self.send(id, ServerPacket::Pong(ping)),
+            ClientPacket::Pong(_) | ClientPacket::NoOp => {}
+        }
+    }
+
+    fn login(
+        &mut self,
+        id: ConnId,
+        login_id: String,
+        nickname: String,
+        group: String,
+        command: &str,
+    ) {
+        match command {
+            // List the users and end the session, without logging in.
+            packets::LOGIN_W => {
+                self.send(id, ServerPacket::Login);
+                self.who(id, None);
+                self.send(id, ServerPacket::Exit);
+                self.close(id);
+                return;
+            }
+            packets::LOGIN_LOGIN => {}
+            _ => {
+                self.error(id, &format!("Unknown login command {}.", command));
+                self.close(id);
+                return;
+            }
+        }
+
+        if let Err(reason) = self.check_nickname(&nickname) {
+            self.error(id, &reason);
+            self.close(id);
+            return;
+        }
+
+        let group = if group.is_empty() {
+            self.config.default_group.clone()
+        } else {
+            group
+        };
+        if !valid_group(&group) {
+            self.error(id, "Invalid group name.");
+            self.close(id);
+            return;
+        }
+
+        let login_time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() as i64)
+            .unwrap_or_default();
+        if let Some(conn) = self.conns.get_mut(&id) {
+            conn.user = Some(User {
+                nickname,
+                login_id,
+                group: String::new(),
+                login_time,
+                last_active: Instant::now(),
+                nobeep: NoBeepMode::Off,
+            });
+        }
+
+        self.send(id, ServerPacket::Login);
+        let message = format!("{} entered group", self.ident(id));
+        self.join(id, &group, StatusCategory::SignOn, &message);
+    }
+
+    /// Check whether `nickname` is valid and not in use by another user.
+    fn check_nickname(&self, nickname: &str) -> Result<(), String> {
+        if nickname.is_empty() {
+            return Err("Nickname is empty.".to_string());
+        }
+        if nickname.len() > MAX_NICKNAME_LEN {
+            return Err(format!(
+                "Nickname is longer than {} characters.",
+                MAX_NICKNAME_LEN
+            ));
+        }
+        if !nickname
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err("Nickname contains invalid characters.".to_string());
+        }
+        if self.find(nickname).is_some() {
+            return Err("Nickname already in use.".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Put the user in `group`, which is created with the user as its moderator if it
+    /// doesn't exist yet, and let the other members know with `message`.
+    fn join(&mut self, id: ConnId, group: &str, category: StatusCategory, message: &str) {
+        self.announce(group, None, category, message);
+
+        let moderator = !self.groups.contains_key(group);
+        self.groups
+            .entry(group.to_string())
+            .or_insert_with(|| Group {
+                topic: None,
+                moderator: Some(id),
+            });
+        if let Some(user) = self.user_mut(id) {
+            user.group = group.to_string();
+        }
+
+        let status = if moderator {
+            format!("You are now in group {} as moderator", group)
+        } else {
+            format!("You are now in group {}", group)
+        };
+        self.status(id, StatusCategory::Status, &status);
+    }
+
+    /// Take the user out of their group and let the remaining members know with
+    /// `message`. Groups are removed once they're empty.
+    fn leave(&mut self, id: ConnId, category: StatusCategory, message: &str) {
+        let group = match self.user_mut(id) {
+            Some(user) => std::mem::take(&mut user.group),
+            None => return,
+        };
+
+        self.announce(&group, Some(id), category, message);
+
+        if self.members(&group).is_empty() {
+            self.groups.remove(&group);
+        } else if let Some(g) = self.groups.get_mut(&group) {
+            if g.moderator == Some(id) {
+                g.moderator = None;
+            }
+        }
+    }
+
+    fn open(&self, id: ConnId, message: &str) {
+        let (nickname, group) = match self.user(id) {
+            Some(user) => (user.nickname.clone(), user.group.clone()),
+            None => return,
+        };
+
+        for part in packets::split_text(message, MAX_DATA_LEN - nickname.len() - 1) {
+            for member in self.members(&group) {
+                if member != id {
+                    self.send(
+                        member,
+                        ServerPacket::Open {
+                            nickname: nickname.clone(),
+                            message: part.to_string(),
+                        },
+                    );
+                }
+            }
+        }
+    }
+
+    fn command(&mut self, id: ConnId, command: &str, args: &str) {
+        match command {
+            packets::CMD_M | packets::CMD_MSG => self.personal(id, args),
+            packets::CMD_BEEP => self.beep(id, args),
+            packets::CMD_NOBEEP => self.nobeep(id, args),
+            packets::CMD_NAME => self.rename(id, args),
+            packets::CMD_G => self.change_group(id, args),
+            packets::CMD_W => self.who(id, Some(args).filter(|g| !g.is_empty())),
+            packets::CMD_TOPIC => self.topic(id, args),
+            packets::CMD_PASS => self.pass(id, args),
+            packets::CMD_BOOT => self.boot(id, args),
+            _ => self.error(id, &format!("Unsupported command {}.", command)),
+        }
+    }
+
+    fn personal(&self, id: ConnId, args: &str) {
+        let (recipient, message) = match args.split_once(char::is_whitespace) {
+            Some((recipient, message)) if !message.trim().is_empty() => {
+                (recipient, message.trim_start())
+            }
+            _ => return self.error(id, "Usage: m nickname message"),
+        };
+
+        let to = match self.find(recipient) {
+            Some(to) => to,
+            None => return self.error(id, &format!("{} is not signed on.", recipient)),
+        };
+
+        let nickname = self.nickname(id);
+        for part in packets::split_text(message, MAX_DATA_LEN - nickname.len() - 1) {
+            self.send(
+                to,
+                ServerPacket::Personal {
+                    nickname: nickname.clone(),
+                    message: part.to_string(),
+                },
+            );
+        }
+    }
+
+    fn beep(&self, id: ConnId, recipient: &str) {
+        let to = match self.find(recipient) {
+            Some(to) => to,
+            None => return self.error(id, &format!("{} is not signed on.", recipient)),
+        };
+
+        let nickname = self.nickname(id);
+        match self.user(to).map(|user| user.nobeep) {
+            Some(NoBeepMode::Off) => self.send(to, ServerPacket::Beep(nickname)),
+            Some(mode) => {
+                self.error(id, &format!("{} has nobeep enabled.", recipient));
+                if mode == NoBeepMode::Verbose {
+                    let message = format!("{} attempted (and failed) to beep you", nickname);
+                    self.status(to, StatusCategory::NoBeep, &message);
+                }
+            }
+            None => {}
+        }
+    }
+
+    fn nobeep(&mut self, id: ConnId, args: &str) {
+        let mode = match NoBeepMode::from_arg(args) {
+            Some(mode) => mode,
+            None => return self.error(id, "Usage: nobeep on|off|verbose"),
+        };
+
+        if let Some(user) = self.user_mut(id) {
+            user.nobeep = mode;
+        }
+        self.status(
+            id,
+            StatusCategory::NoBeep,
+            &format!("No-Beep {}", mode.arg()),
+        );
+    }
+
+    fn rename(&mut self, id: ConnId, nickname: &str) {
+        let old = self.nickname(id);
+        // Changing the case of one's own nickname is fine.
+        let check = if nickname.eq_ignore_ascii_case(&old) && !nickname.is_empty() {
+            Ok(())
+        } else {
+            self.check_nickname(nickname)
+        };
+        if let Err(reason) = check {
+            return self.error(id, &reason);
+        }
+
+        let group = match self.user_mut(id) {
+            Some(user) => {
+                user.nickname = nickname.to_string();
+                user.group.clone()
+            }
+            None => return,
+        };
+
+        let message = format!("{} changed nickname to {}", old, nickname);
+        self.announce(&group, None, StatusCategory::Name, &message);
+    }
+
+    fn change_group(&mut self, id: ConnId, group: &str) {
+        if !valid_group(group) {
+            return self.error(id, "Usage: g group");
+        }
+        if self.user(id).is_some_and(|user| user.group == group) {
+            return self.error(id, &format!("You are already in group {}.", group));
+        }
+
+        let ident = self.ident(id);
+        self.leave(id, StatusCategory::Depart, &format!("{} just left", ident));
+        self.join(
+            id,
+            group,
+            StatusCategory::Arrive,
+            &format!("{} entered group", ident),
+        );
+    }
+
+    /// Send a who-listing of all groups, or just `only`.
+    fn who(&self, id: ConnId, only: Option<&str>) {
+        let mut names: Vec<_> = self
+            .groups
+            .keys()
+            .filter(|name| only.is_none_or(|only| *name == only))
+            .collect();
+        names.sort();
+
+        let now = Instant::now();
+        let mut users = 0;
+        for name in &names {
+            let group = &self.groups[*name];
+            let moderator = group
+                .moderator
+                .and_then(|m| self.user(m))
+                .map(|user| user.nickname.as_str())
+                .unwrap_or("(None)");
+            let topic = group.topic.as_deref().unwrap_or("(None)");
+            self.output(
+                id,
+                &format!(
+                    "Group: {}  (pvl) Mod: {}  Topic: {}",
+                    name, moderator, topic
+                ),
+            );
+
+            for member in self.members(name) {
+                let (conn, user) = match self.conns.get(&member) {
+                    Some(
+                        conn @ Conn {
+                            user: Some(user), ..
+                        },
+                    ) => (conn, user),
+                    _ => continue,
+                };
+                users += 1;
+                self.send(
+                    id,
+                    ServerPacket::CommandOutput(CommandOutput::WhoLine(UserInfo {
+                        nickname: user.nickname.clone(),
+                        moderator: group.moderator == Some(member),
+                        idle: now.duration_since(user.last_active).as_secs(),
+                        login_time: user.login_time,
+                        username: user.login_id.clone(),
+                        hostname: conn.hostname.clone(),
+                        registered: false,
+                    })),
+                );
+            }
+        }
+
+        self.output(
+            id,
+            &format!("Total: {} users in {} groups", users, names.len()),
+        );
+    }
+
+    fn topic(&mut self, id: ConnId, topic: &str) {
+        let group = match self.user(id) {
+            Some(user) => user.group.clone(),
+            None => return,
+        };
+
+        if topic.is_empty() {
+            let message = match self.groups.get(&group).and_then(|g| g.topic.as_deref()) {
+                Some(topic) => format!("The topic is: {}", topic),
+                None => "The topic is not set.".to_string(),
+            };
+            return self.output(id, &message);
+        }
+
+        if topic.len() > MAX_TOPIC_LEN {
+            return self.error(
+                id,
+                &format!("Topic is longer than {} characters.", MAX_TOPIC_LEN),
+            );
+        }
+
+        // Anyone can set the topic of a group without a moderator.
+        let allowed = self
+            .groups
+            .get(&group)
+            .is_some_and(|g| g.moderator.is_none() || g.moderator == Some(id));
+        if !allowed {
+            return self.error(id, "You aren't the moderator.");
+        }
+
+        if let Some(g) = self.groups.get_mut(&group) {
+            g.topic = Some(topic.to_string());
+        }
+        let message = format!("{} changed the topic to \"{}\"", self.nickname(id), topic);
+        self.announce(&group, None, StatusCategory::Topic, &message);
+    }
+
+    /// Look up a member of the moderator's group for `pass` and `boot`.
+    fn moderated_member(&self, id: ConnId, nickname: &str, usage: &str) -> Option<ConnId> {
+        if nickname.is_empty() {
+            self.error(id, usage);
+            return None;
+        }
+        if !self.is_moderator(id) {
+            self.error(id, "You aren't the moderator.");
+            return None;
+        }
+
+        let group = self.user(id).map(|user| user.group.as_str());
+        match self.find(nickname) {
+            Some(member) if self.user(member).map(|user| user.group.as_str()) == group => {
+                Some(member)
+            }
+            _ => {
+                self.error(id, &format!("{} is not in your group.", nickname));
+                None
+            }
+        }
+    }
+
+    fn pass(&mut self, id: ConnId, nickname: &str) {
+        let member = match self.moderated_member(id, nickname, "Usage: pass nickname") {
+            Some(member) => member,
+            None => return,
+        };
+
+        let group = match self.user(id) {
+            Some(user) => user.group.clone(),
+            None => return,
+        };
+        if let Some(g) = self.groups.get_mut(&group) {
+            g.moderator = Some(member);
+        }
+
+        let message = format!(
+            "{} has passed moderation to {}",
+            self.nickname(id),
+            self.nickname(member)
+        );
+        self.announce(
+            &group,
+            None,
+            StatusCategory::Other("Pass".to_string()),
+            &message,
+        );
+    }
+
+    fn boot(&mut self, id: ConnId, nickname: &str) {
+        let member = match self.moderated_member(id, nickname, "Usage: boot nickname") {
+            Some(member) => member,
+            None => return,
+        };
+        if member == id {
+            return self.error(id, "You can't boot yourself.");
+        }
+
+        let booted = self.nickname(member);
+        self.status(
+            member,
+            StatusCategory::Boot,
+            &format!("You were booted by {}.", self.nickname(id)),
+        );
+        self.leave(
+            member,
+            StatusCategory::Boot,
+            &format!("{} was booted.", booted),
+        );
+        let message = format!("{} entered group", self.ident(member));
+        self.join(member, BOOT_GROUP, StatusCategory::Arrive, &message);
+    }
+}
+
+/// Whether `group` can be used as a group name. Names are sent as a single word in
+/// status messages and who-listings, so they can't contain whitespace.
+/// The longest prefix of `message` that fits in a packet after `used` bytes of other
+/// fields.
+fn truncate(message: &str, used: usize) -> &str {
+    let mut end = message.len().min(MAX_DATA_LEN.saturating_sub(used));
+    while !message.is_char_boundary(end) {
+        end -= 1;
+    }
+    &message[..end]
+}
+
+fn valid_group(group: &str) -> bool {
+    !group.is_empty() && !group.contains(char::is_whitespace)
+}
@@ -0,0 +1,482 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::time::{Duration, Instant};
+
+use tracing::trace;
+
+use crate::codec::{Framer, Reassembler};
+use crate::packets::{self, ClientPacket, CommandOutput, EncodeError, ServerPacket};
+use crate::transcript::Recorder;
+use crate::{
+    joined_group, Command, Config, DisconnectReason, Encoding, Error, Event, GroupInfo,
+    StatusCategory, WhoListing, REDACTED,
+};
+
+/// What to do after handling a command or packet.
+#[derive(Debug)]
+pub(crate) enum Action {
+    /// Send these encoded packets to the server, if any.
+    Send(Vec<Vec<u8>>),
+    /// End the session.
+    Disconnect(DisconnectReason),
+}
+
+/// Progress of the who-listing after adding command output to it.
+enum WhoProgress {
+    /// The output is not part of a listing.
+    NotPart,
+    /// The output was added, more is expected.
+    Collecting,
+    /// The listing is complete.
+    Done(WhoListing),
+}
+
+/// State of an ICB session which is independent of how the connection is driven,
+/// shared by the blocking `Server` and the async `IcbConnection`.
+pub(crate) struct Session {
+    /// Account to log in as, which stays the same when the nickname changes.
+    login_id: String,
+    /// Current nickname, updated once the server confirms a `Command::Name`.
+    pub(crate) nickname: String,
+    password: Option<String>,
+    pub(crate) group: String,
+    /// Encoding of the text in packets, for both directions.
+    pub(crate) encoding: Encoding,
+    /// Who-listing being collected in response to a `Command::Who`.
+    who: Option<WhoListing>,
+    /// Identifier for the next `Command::Ping`.
+    ping_seq: u32,
+    /// Pings sent by the client which are awaiting a pong, by identifier.
+    pings: HashMap<String, Instant>,
+    /// Send a no-op packet after the connection has been idle for this long.
+    keepalive: Option<Duration>,
+    /// Time a packet was last sent to the server.
+    last_write: Instant,
+}
+
+// Written out to keep the password out of logs.
+impl fmt::Debug for Session {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Session")
+            .field("login_id", &self.login_id)
+            .field("nickname", &self.nickname)
+            .field("password", &self.password.as_ref().map(|_| REDACTED))
+            .field("group", &self.group)
+            .field("encoding", &self.encoding)
+            .field("who", &self.who)
+            .field("ping_seq", &self.ping_seq)
+            .field("pings", &self.pings)
+            .field("keepalive", &self.keepalive)
+            .field("last_write", &self.last_write)
+            .finish()
+    }
+}
+
+impl Session {
+    pub(crate) fn new(config: &Config) -> Session {
+        Session {
+            login_id: config
+                .login_id
+                .clone()
+                .unwrap_or_else(|| config.nickname.clone()),
+            nickname: config.nickname.clone(),
+            password: config.password.clone(),
+            group: config.group.clone(),
+            encoding: config.encoding,
+            who: None,
+            ping_seq: 0,
+            pings: HashMap::new(),
+            keepalive: config.keepalive_interval(),
+            last_write: Instant::now(),
+        }
+    }
+
+    /// Forget about replies that won't arrive anymore, e.g. after reconnecting.
+    pub(crate) fn reset(&mut self) {
+        self.who = None;
+        self.pings.clear();
+    }
+
+    // The login packet with the given login command for the current nickname and group.
+    pub(crate) fn login(&self, command: &str) -> Result<Vec<u8>, EncodeError> {
+        ClientPacket::Login {
+            login_id: self.login_id.clone(),
+            nickname: self.nickname.clone(),
+            group: self.group.clone(),
+            command: command.to_string(),
+            password: self.password.clone(),
+        }
+        .encode_with(self.encoding)
+    }
+
+    /// Check the server's response to the login packet; an empty login packet
+    /// on success or an error if the server refuses the login.
+    pub(crate) fn login_response(packet: ServerPacket) -> Result<(), Error> {
+        match packet {
+            ServerPacket::Login => Ok(()),
+            ServerPacket::Error(reason) => Err(Error::LoginRejected { reason }),
+            packet => Err(Error::Protocol(format!(
+                "expected a login packet, received '{}'",
+                packet.packet_type()
+            ))),
+        }
+    }
+
+    /// Take note of `data` having been sent to the server.
+    pub(crate) fn sent(&mut self, data: &[u8]) {
+        trace!(
+            direction = "sent",
+            packet_type = %(data.get(1).copied().unwrap_or_default() as char),
+            len = data.len(),
+            "packet"
+        );
+        self.last_write = Instant::now();
+    }
+
+    /// Time at which a no-op packet should be sent to keep the connection alive, unless
+    /// something else is sent before then.
+    pub(crate) fn keepalive_deadline(&self) -> Option<Instant> {
+        self.keepalive.map(|interval| self.last_write + interval)
+    }
+
+    /// Start collecting a who-listing, without sending a command for it, as the server
+    /// sends one after a login with the `w` command.
+    pub(crate) fn expect_who(&mut self) {
+        self.who = Some(WhoListing::default());
+    }
+
+    /// Take the who-listing collected so far.
+    pub(crate) fn take_who(&mut self) -> WhoListing {
+        self.who.take().unwrap_or_default()
+    }
+
+    /// Handle a command sent by the client, returning the event for the client (if any)
+    /// and what to do next.
+    pub(crate) fn handle_command(&mut self, command: Command) -> (Option<Event>, Action) {
+        match self.command(command) {
+            Ok(action) => (None, action),
+            // Let the client know the command couldn't be sent.
+            Err(e) => (Some(Event::Error(e.to_string())), Action::Send(Vec::new())),
+        }
+    }
+
+    /// Turn a command sent by the client into the packets to send.
+    /// All packets are encoded before any are sent, so a message is either sent
+    /// completely or not at all.
+    pub(crate) fn command(&mut self, command: Command) -> Result<Action, EncodeError> {
+        let packets = match command {
+            Command::Bye => return Ok(Action::Disconnect(DisconnectReason::Quit)),
+            Command::Open(msg) => ClientPacket::open(&msg),
+            Command::Personal(recipient, msg) => ClientPacket::personal(&recipient, &msg)?,
+            Command::Beep(recipient) => vec![ClientPacket::command(packets::CMD_BEEP, &recipient)],
+            Command::Name(newname) => vec![ClientPacket::command(packets::CMD_NAME, &newname)],
+            Command::Group(group) => vec![ClientPacket::command(packets::CMD_G, &group)],
+            Command::Topic(topic) => vec![ClientPacket::command(packets::CMD_TOPIC, &topic)],
+            Command::Boot(nickname) => vec![ClientPacket::command(packets::CMD_BOOT, &nickname)],
+            Command::Pass(nickname) => vec![ClientPacket::command(packets::CMD_PASS, &nickname)],
+            Command::GroupStatus(status) => {
+                vec![ClientPacket::command(packets::CMD_STATUS, &status.args())]
+            }
+            Command::Ping => {
+                let id = self.ping_seq.to_string();
+                self.ping_seq = self.ping_seq.wrapping_add(1);
+                self.pings.insert(id.clone(), Instant::now());
+                vec![ClientPacket::Ping(id)]
+            }
+            Command::Register(password) => vec![ClientPacket::command(packets::CMD_P, &password)],
+            Command::NoBeep(mode) => vec![ClientPacket::command(packets::CMD_NOBEEP, mode.arg())],
+            Command::Raw { cmd, args } => {
+                let packet =
+                    ClientPacket::command(&cmd, &args).encode_unchecked_with(self.encoding)?;
+                return Ok(Action::Send(vec![packet]));
+            }
+            Command::Who(group) => {
+                self.who = Some(WhoListing::default());
+                vec![ClientPacket::command(
+                    packets::CMD_W,
+                    group.as_deref().unwrap_or(""),
+                )]
+            }
+        };
+
+        let data = packets
+            .iter()
+            .map(|packet| packet.encode_with(self.encoding))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Action::Send(data))
+    }
+
+    /// Handle a packet sent by the server, returning the event for the client (if any)
+    /// and what to do next.
+    pub(crate) fn packet(
+        &mut self,
+        packet: ServerPacket,
+    ) -> Result<(Option<Event>, Action), EncodeError> {
+        let nothing = Action::Send(Vec::new());
+
+        match &packet {
+            ServerPacket::Status {
+                category: StatusCategory::Status,
+                message,
+            } => {
+                if let Some(group) = joined_group(message) {
+                    self.group = group.to_string();
+                }
+            }
+            // The server announces the new nickname to the group, e.g.
+            // "alice changed nickname to bob"; it may refuse the change with an error.
+            ServerPacket::Status {
+                category: StatusCategory::Name,
+                message,
+            } => {
+                let prefix = format!("{} changed nickname to ", self.nickname);
+                if let Some(newname) = message.strip_prefix(&prefix) {
+                    self.nickname = newname.trim().to_string();
+                }
+            }
+            // Output that is part of a who-listing is sent once the listing
+            // is complete.
+            ServerPacket::CommandOutput(output) => match self.collect_who(output) {
+                WhoProgress::NotPart => {}
+                WhoProgress::Collecting => return Ok((None, nothing)),
+                WhoProgress::Done(listing) => {
+                    return Ok((Some(Event::WhoListing(listing)), nothing))
+                }
+            },
+            // The server refused the who command, e.g. for an unknown group, so the
+            // listing won't arrive.
+            ServerPacket::Error(_) if self.who.is_some() => self.who = None,
+            ServerPacket::Exit => return Ok((None, Action::Disconnect(DisconnectReason::Exit))),
+            ServerPacket::Ping(id) => {
+                let pong = ClientPacket::Pong(id.clone()).encode_with(self.encoding)?;
+                return Ok((None, Action::Send(vec![pong])));
+            }
+            ServerPacket::Pong(id) => {
+                if let Some(sent) = self.pings.remove(id) {
+                    let event = Event::Pong {
+                        id: id.clone(),
+                        latency: sent.elapsed(),
+                    };
+                    return Ok((Some(event), nothing));
+                }
+            }
+            _ => {}
+        }
+
+        Ok((Event::from_packet(packet), nothing))
+    }
+
+    /// Add command output to the who-listing that is being collected.
+    fn collect_who(&mut self, output: &CommandOutput) -> WhoProgress {
+        let listing = match self.who.as_mut() {
+            Some(l) => l,
+            None => return WhoProgress::NotPart,
+        };
+
+        match output {
+            CommandOutput::WhoHeader => {}
+            CommandOutput::WhoLine(user) => listing.add_user(user.clone()),
+            CommandOutput::GroupHeader { name, topic } => listing.groups.push(GroupInfo {
+                name: name.clone(),
+                topic: topic.clone(),
+                ..Default::default()
+            }),
+            CommandOutput::Output(message) if message.starts_with("Group: ") => {
+                if let Some(group) = GroupInfo::from_header(message) {
+                    listing.groups.push(group);
+                }
+            }
+            // The listing ends with either an explicit end of command or
+            // with the summary line.
+            CommandOutput::End(_) => return WhoProgress::Done(self.who.take().unwrap_or_default()),
+            CommandOutput::Output(message) if message.starts_with("Total: ") => {
+                return WhoProgress::Done(self.who.take().unwrap_or_default())
+            }
+            _ => return WhoProgress::NotPart,
+        }
+
+        WhoProgress::Collecting
+    }
+}
+
+/// Turns the data received from the server into packets, independent of how the
+/// connection is read.
+#[derive(Debug)]
+pub(crate) struct Incoming {
+    framer: Framer,
+    reassembler: Reassembler,
+    encoding: Encoding,
+    transcript: Option<Recorder>,
+}
+
+impl Incoming {
+    pub(crate) fn new(
+        max_packet_len: usize,
+        encoding: Encoding,
+        transcript: Option<Recorder>,
+    ) -> Incoming {
+        Incoming {
+            framer: Framer::new(),
+            reassembler: Reassembler::new(max_packet_len),
+            encoding,
+            transcript,
+        }
+    }
+
+    /// Add data that was read from the connection.
+    #[cfg(feature = "tokio")]
+    pub(crate) fn push(&mut self, data: &[u8]) {
+        self.framer.push(data);
+    }
+
+    /// Read more data from `reader`, returning the number of bytes read.
+    pub(crate) fn fill<R: Read>(&mut self, reader: &mut R) -> io::Result<usize> {
+        self.framer.fill(reader)
+    }
+
+    /// Take the next packet that was received in its entirety, if any, without decoding
+    /// it. Anything that doesn't make up a complete packet yet is kept until more data
+    /// arrives.
+    pub(crate) fn next_message(&mut self) -> Result<Option<Vec<u8>>, Error> {
+        while let Some(frame) = self.framer.next_frame() {
+            if let Some(transcript) = &self.transcript {
+                transcript.record_frame(&frame);
+            }
+            let message = self
+                .reassembler
+                .add(frame)
+                .map_err(|e| Error::Protocol(e.to_string()))?;
+            // Otherwise it's part of an extended packet, continue with the next part.
+            if let Some(message) = message {
+                trace!(
+                    direction = "received",
+                    packet_type = %(message.first().copied().unwrap_or_default() as char),
+                    len = message.len(),
+                    "packet"
+                );
+                return Ok(Some(message));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Decode a message taken with `next_message()`.
+    pub(crate) fn decode(&self, message: &[u8]) -> Result<ServerPacket, Error> {
+        Ok(ServerPacket::decode_with(message, self.encoding)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session::new(&Config {
+            nickname: "tester".to_string(),
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn error_ends_who_listing() {
+        let mut session = session();
+        session
+            .command(Command::Who(Some("nosuchgroup".to_string())))
+            .unwrap();
+
+        let (event, _) = session
+            .packet(ServerPacket::Error("The group doesn't exist.".to_string()))
+            .unwrap();
+        assert!(matches!(event, Some(Event::Error(_))));
+
+        // Unrelated command output afterwards is passed on as is.
+        let (event, _) = session
+            .packet(ServerPacket::CommandOutput(CommandOutput::End(None)))
+            .unwrap();
+        assert!(matches!(
+            event,
+            Some(Event::CommandOutput(CommandOutput::End(None)))
+        ));
+    }
+
+    #[test]
+    fn who_listing_is_collected() {
+        let mut session = session();
+        session.command(Command::Who(None)).unwrap();
+
+        let (event, _) = session
+            .packet(ServerPacket::CommandOutput(CommandOutput::WhoHeader))
+            .unwrap();
+        assert!(event.is_none());
+
+        let (event, _) = session
+            .packet(ServerPacket::CommandOutput(CommandOutput::End(None)))
+            .unwrap();
+        match event {
+            Some(Event::WhoListing(listing)) => assert_eq!(listing, WhoListing::default()),
+            event => panic!("expected a who-listing, got {:?}", event),
+        }
+    }
+
+    fn login_packet(session: &Session) -> ClientPacket {
+        let data = session.login(packets::LOGIN_LOGIN).unwrap();
+        ClientPacket::decode(&data[1..]).unwrap()
+    }
+
+    fn name_status(message: &str) -> ServerPacket {
+        ServerPacket::Status {
+            category: StatusCategory::Name,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn refused_rename_keeps_nickname() {
+        let mut session = session();
+        session.command(Command::Name("taken".to_string())).unwrap();
+        session
+            .packet(ServerPacket::Error("Nickname already in use.".to_string()))
+            .unwrap();
+
+        match login_packet(&session) {
+            ClientPacket::Login {
+                login_id, nickname, ..
+            } => {
+                assert_eq!(login_id, "tester");
+                assert_eq!(nickname, "tester");
+            }
+            packet => panic!("expected a login packet, got {:?}", packet),
+        }
+    }
+
+    #[test]
+    fn confirmed_rename_keeps_login_id() {
+        let mut session = session();
+        session
+            .command(Command::Name("newname".to_string()))
+            .unwrap();
+        // Renames of other users don't affect the session.
+        session
+            .packet(name_status("someone changed nickname to other"))
+            .unwrap();
+        assert_eq!(session.nickname, "tester");
+
+        session
+            .packet(name_status("tester changed nickname to newname"))
+            .unwrap();
+        assert_eq!(session.nickname, "newname");
+
+        match login_packet(&session) {
+            ClientPacket::Login {
+                login_id, nickname, ..
+            } => {
+                assert_eq!(login_id, "tester");
+                assert_eq!(nickname, "newname");
+            }
+            packet => panic!("expected a login packet, got {:?}", packet),
+        }
+    }
+}
@@ -0,0 +1,270 @@
+//! Helpers for testing ICB clients, available with the `testing` feature.
+//!
+//! `MockServer` stands in for an ICB server on a local port: it greets clients with a
+//! protocol packet, accepts their login and records every packet they send, while the
+//! test decides what the server sends back.
+
+use crossbeam_channel::{select, unbounded, Receiver, Sender};
+use std::io::{self, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::codec::{ClientReader, DEFAULT_MAX_PACKET_LEN};
+use crate::packets::{ClientPacket, CommandOutput, ServerPacket};
+use crate::{Config, StatusCategory};
+
+/// How a `MockServer` responds to clients.
+#[derive(Clone, Debug)]
+pub struct MockConfig {
+    /// Packet sent to every client upon connecting.
+    pub protocol: ServerPacket,
+    /// Refuse logins with this error message instead of accepting them.
+    pub login_error: Option<String>,
+}
+
+impl Default for MockConfig {
+    fn default() -> MockConfig {
+        MockConfig {
+            protocol: ServerPacket::Protocol {
+                level: "1".to_string(),
+                host_id: "localhost".to_string(),
+                server_id: "icb-rs mock server".to_string(),
+            },
+            login_error: None,
+        }
+    }
+}
+
+/// Instructions for the connection that is being served.
+enum Instruction {
+    Send(Vec<u8>),
+    Disconnect,
+}
+
+/// An ICB server on a local port for use in tests.
+///
+/// Clients are served one at a time; after a client disconnects (or is disconnected
+/// with `disconnect()`) the next one is accepted, so reconnecting can be tested too.
+/// Packets injected with `send()` and its shorthands are queued until a client has
+/// logged in. The server stops when the `MockServer` is dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    addr: SocketAddr,
+    instruction_s: Sender<Instruction>,
+    packet_r: Receiver<ClientPacket>,
+    received: Arc<Mutex<Vec<ClientPacket>>>,
+    stop: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Start a server with the default `MockConfig` on a free port.
+    pub fn start() -> io::Result<MockServer> {
+        MockServer::start_with(MockConfig::default())
+    }
+
+    /// Start a server with the given configuration on a free port.
+    pub fn start_with(config: MockConfig) -> io::Result<MockServer> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let addr = listener.local_addr()?;
+
+        let (instruction_s, instruction_r) = unbounded();
+        let (packet_s, packet_r) = unbounded();
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let mut session = MockSession {
+            config,
+            instruction_r,
+            recorder: Recorder {
+                packet_s,
+                received: received.clone(),
+            },
+        };
+        let stopped = stop.clone();
+        thread::spawn(move || {
+            for sock in listener.incoming() {
+                if stopped.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(sock) = sock {
+                    // A client going away is nothing special, just wait for the next.
+                    session.serve(sock).ok();
+                }
+            }
+        });
+
+        Ok(MockServer {
+            addr,
+            instruction_s,
+            packet_r,
+            received,
+            stop,
+        })
+    }
+
+    /// The address the server is listening on.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// A `Config` for connecting to this server with the given nickname.
+    pub fn config(&self, nickname: &str) -> Config {
+        Config {
+            serverip: self.addr.ip().to_string(),
+            port: self.addr.port(),
+            nickname: nickname.to_string(),
+            ..Default::default()
+        }
+    }
+
+    /// Send a packet to the client.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the packet can't be encoded.
+    pub fn send(&self, packet: ServerPacket) {
+        let data = packet.encode().expect("packet can't be encoded");
+        self.instruction_s.send(Instruction::Send(data)).ok();
+    }
+
+    /// Send a message to the group from `nickname`.
+    pub fn open(&self, nickname: &str, message: &str) {
+        self.send(ServerPacket::Open {
+            nickname: nickname.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Send a personal message from `nickname`.
+    pub fn personal(&self, nickname: &str, message: &str) {
+        self.send(ServerPacket::Personal {
+            nickname: nickname.to_string(),
+            message: message.to_string(),
+        });
+    }
+
+    /// Send a status message.
+    pub fn status(&self, category: StatusCategory, message: &str) {
+        self.send(ServerPacket::Status {
+            category,
+            message: message.to_string(),
+        });
+    }
+
+    /// Send an error message.
+    pub fn error(&self, message: &str) {
+        self.send(ServerPacket::Error(message.to_string()));
+    }
+
+    /// Send command output, such as a line of a who-listing.
+    pub fn command_output(&self, output: CommandOutput) {
+        self.send(ServerPacket::CommandOutput(output));
+    }
+
+    /// Close the connection to the current client, after sending everything that was
+    /// queued before.
+    pub fn disconnect(&self) {
+        self.instruction_s.send(Instruction::Disconnect).ok();
+    }
+
+    /// Wait for the next packet sent by a client, including the login packet.
+    pub fn next_packet(&self, timeout: Duration) -> Option<ClientPacket> {
+        self.packet_r.recv_timeout(timeout).ok()
+    }
+
+    /// Every packet the clients have sent so far, in the order they were received.
+    pub fn received(&self) -> Vec<ClientPacket> {
+        self.received
+            .lock()
+            .map(|received| received.clone())
+            .unwrap_or_default()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.instruction_s.send(Instruction::Disconnect).ok();
+        // Wake up the listener so it notices it should stop.
+        TcpStream::connect(self.addr).ok();
+    }
+}
+
+/// Keeps track of the packets sent by clients.
+#[derive(Clone)]
+struct Recorder {
+    packet_s: Sender<ClientPacket>,
+    received: Arc<Mutex<Vec<ClientPacket>>>,
+}
+
+impl Recorder {
+    fn record(&self, packet: ClientPacket) {
+        if let Ok(mut received) = self.received.lock() {
+            received.push(packet.clone());
+        }
+        self.packet_s.send(packet).ok();
+    }
+}
+
+/// The server side of a `MockServer`, which talks to the clients.
+struct MockSession {
+    config: MockConfig,
+    instruction_r: Receiver<Instruction>,
+    recorder: Recorder,
+}
+
+impl MockSession {
+    /// Talk to a single client until either side ends the connection.
+    fn serve(&mut self, mut sock: TcpStream) -> io::Result<()> {
+        sock.write_all(&encode(&self.config.protocol)?)?;
+
+        let mut reader = ClientReader::new(sock.try_clone()?, DEFAULT_MAX_PACKET_LEN);
+
+        let login = reader.read()?;
+        let accepted =
+            matches!(login, ClientPacket::Login { .. }) && self.config.login_error.is_none();
+        self.recorder.record(login);
+
+        if !accepted {
+            let reason = self
+                .config
+                .login_error
+                .clone()
+                .unwrap_or_else(|| "Expected a login packet".to_string());
+            sock.write_all(&encode(&ServerPacket::Error(reason))?)?;
+            return sock.shutdown(Shutdown::Both);
+        }
+        sock.write_all(&encode(&ServerPacket::Login)?)?;
+
+        // Record everything the client sends from here on in the background.
+        let (done_s, done_r) = unbounded::<()>();
+        let recorder = self.recorder.clone();
+        thread::spawn(move || {
+            while let Ok(packet) = reader.read() {
+                recorder.record(packet);
+            }
+            drop(done_s);
+        });
+
+        loop {
+            select! {
+                recv(self.instruction_r) -> instruction => match instruction {
+                    Ok(Instruction::Send(data)) => sock.write_all(&data)?,
+                    Ok(Instruction::Disconnect) | Err(_) => break,
+                },
+                recv(done_r) -> _ => break,
+            }
+        }
+
+        sock.shutdown(Shutdown::Both)
+    }
+}
+
+fn encode(packet: &ServerPacket) -> io::Result<Vec<u8>> {
+    packet
+        .encode()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
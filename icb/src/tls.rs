@@ -0,0 +1,153 @@
+use std::convert::TryFrom;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{self, CryptoProvider};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, ServerName, UnixTime};
+use rustls::{
+    ClientConfig, ClientConnection, DigitallySignedStruct, RootCertStore, SignatureScheme,
+};
+
+use crate::transport::Stream;
+use crate::Error;
+
+/// TLS settings for servers that are only reachable over TLS, such as those behind
+/// stunnel. Requires the `tls` feature.
+#[derive(Clone, Debug, Default)]
+pub struct TlsConfig {
+    /// Name sent with SNI and verified against the server's certificate; defaults to
+    /// `Config::serverip`.
+    pub server_name: Option<String>,
+    /// PEM file with the CA certificates to trust, instead of the bundled web PKI roots.
+    pub ca_file: Option<PathBuf>,
+    /// Accept any certificate the server presents. This leaves the connection open to
+    /// interception, so only use it for testing.
+    pub insecure: bool,
+}
+
+/// Sets up TLS on newly connected sockets.
+#[derive(Debug)]
+pub(crate) struct Connector {
+    config: Arc<ClientConfig>,
+    server_name: ServerName<'static>,
+}
+
+impl Connector {
+    pub(crate) fn new(tls: &TlsConfig, serverip: &str) -> Result<Connector, Error> {
+        let provider = Arc::new(crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()
+            .map_err(|e| Error::Tls(e.to_string()))?;
+
+        let config = if tls.insecure {
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(NoVerifier(provider)))
+                .with_no_client_auth()
+        } else {
+            builder
+                .with_root_certificates(root_store(tls)?)
+                .with_no_client_auth()
+        };
+
+        let name = tls.server_name.as_deref().unwrap_or(serverip);
+        let server_name = ServerName::try_from(name.to_string())
+            .map_err(|_| Error::Tls(format!("invalid server name '{}'", name)))?;
+
+        Ok(Connector {
+            config: Arc::new(config),
+            server_name,
+        })
+    }
+
+    /// Perform the TLS handshake on `sock`.
+    pub(crate) fn connect(&self, mut sock: TcpStream) -> Result<Stream, Error> {
+        let mut conn = ClientConnection::new(self.config.clone(), self.server_name.clone())
+            .map_err(|e| Error::Tls(e.to_string()))?;
+
+        while conn.is_handshaking() {
+            conn.complete_io(&mut sock)?;
+        }
+
+        Ok(Stream::Tls {
+            sock,
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+}
+
+/// The CA certificates from `TlsConfig::ca_file`, or the web PKI roots.
+fn root_store(tls: &TlsConfig) -> Result<RootCertStore, Error> {
+    let mut roots = RootCertStore::empty();
+
+    match &tls.ca_file {
+        Some(path) => {
+            let certs = CertificateDer::pem_file_iter(path)
+                .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+                .map_err(|e| Error::Tls(format!("{}: {}", path.display(), e)))?;
+            let (added, _) = roots.add_parsable_certificates(certs);
+            if added == 0 {
+                return Err(Error::Tls(format!(
+                    "{}: no usable CA certificates",
+                    path.display()
+                )));
+            }
+        }
+        None => roots.extend(webpki_roots::TLS_SERVER_ROOTS.iter().cloned()),
+    }
+
+    Ok(roots)
+}
+
+/// Certificate verifier for `TlsConfig::insecure`, which accepts any certificate while
+/// still checking the handshake signatures.
+#[derive(Debug)]
+struct NoVerifier(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
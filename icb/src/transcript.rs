@@ -0,0 +1,299 @@
+//! Transcripts of the packets exchanged with a server.
+//!
+//! With `Config::transcript` set, every packet is written to a file as it's sent or
+//! received. Each line holds the time in seconds since the epoch, the direction (`>`
+//! for sent, `<` for received) and the packet as hex, exactly as it went over the wire:
+//!
+//! ```text
+//! 1700000000.123456 < 156a3101646576626f7801696362642d727320302e3100
+//! ```
+//!
+//! A transcript can be read back with `load()`, or played back with `Config::replay`
+//! in place of a connection to reproduce a session offline. Only the received packets
+//! are played back, as fast as they can be read; output the client would collect in
+//! response to its own commands, such as a who-listing, is passed on as plain command
+//! output unless those commands are sent again.
+//!
+//! The password of sent login packets and of the `p` command is replaced with
+//! `********`, so transcripts can be shared without giving it away.
+
+use std::borrow::Cow;
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::codec::{Frame, EXTENDED};
+use crate::packets::{CMD_P, T_COMMAND, T_LOGIN};
+use crate::transport::Stream;
+
+/// Recorded in place of passwords.
+const REDACTED: &[u8] = b"********";
+
+/// Whether a packet was sent or received by the client.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+impl Direction {
+    fn tag(self) -> &'static str {
+        match self {
+            Direction::Sent => ">",
+            Direction::Received => "<",
+        }
+    }
+}
+
+/// A packet as recorded in a transcript.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TranscriptEntry {
+    /// Time the packet was sent or received, since the epoch.
+    pub time: Duration,
+    pub direction: Direction,
+    /// The packet including its length byte. Extended packets are recorded per part.
+    pub data: Vec<u8>,
+}
+
+/// Read all entries from a transcript. Empty lines and lines starting with `#` are
+/// skipped.
+pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Vec<TranscriptEntry>> {
+    let file = BufReader::new(File::open(path)?);
+    let mut entries = Vec::new();
+
+    for (n, line) in file.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let entry = parse_line(line).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid transcript entry on line {}", n + 1),
+            )
+        })?;
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+fn parse_line(line: &str) -> Option<TranscriptEntry> {
+    let mut fields = line.split_whitespace();
+
+    let time = parse_time(fields.next()?)?;
+    let direction = match fields.next()? {
+        ">" => Direction::Sent,
+        "<" => Direction::Received,
+        _ => return None,
+    };
+
+    let hex = fields.next()?;
+    if hex.len() % 2 != 0 || fields.next().is_some() {
+        return None;
+    }
+    let data = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<_>>>()?;
+
+    Some(TranscriptEntry {
+        time,
+        direction,
+        data,
+    })
+}
+
+/// Parse a time such as "1700000000.123456", up to microsecond precision.
+fn parse_time(time: &str) -> Option<Duration> {
+    let (secs, fraction) = time.split_once('.').unwrap_or((time, ""));
+    if fraction.len() > 6 || !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    let micros = format!("{:0<6}", fraction).parse().ok()?;
+    Some(Duration::from_secs(secs.parse().ok()?) + Duration::from_micros(micros))
+}
+
+/// The packets received in the transcript at `path`, as a stream to read them from
+/// in place of a connection. Anything written to it is discarded.
+pub(crate) fn replay(path: &Path) -> io::Result<Stream> {
+    let received: Vec<u8> = load(path)?
+        .into_iter()
+        .filter(|entry| entry.direction == Direction::Received)
+        .flat_map(|entry| entry.data)
+        .collect();
+
+    Ok(Stream::Replay(Arc::new(Mutex::new(io::Cursor::new(
+        received,
+    )))))
+}
+
+/// Replace the password in a login packet or `p` command with `REDACTED`. Other
+/// packets are returned as is.
+fn redact(data: &[u8]) -> Cow<'_, [u8]> {
+    let (&len, rest) = match data.split_first() {
+        Some(split) => split,
+        None => return Cow::Borrowed(data),
+    };
+    // Passwords are short enough to never end up in an extended packet.
+    let (&packet_type, body) = match rest.split_first() {
+        Some(split) if len != EXTENDED && usize::from(len) == rest.len() => split,
+        _ => return Cow::Borrowed(data),
+    };
+    let body = body.strip_suffix(&[0]).unwrap_or(body);
+
+    let mut fields: Vec<&[u8]> = body.split(|b| *b == 1).collect();
+    let secret = match char::from(packet_type) {
+        T_LOGIN => 4,
+        T_COMMAND if fields[0] == CMD_P.as_bytes() => 1,
+        _ => return Cow::Borrowed(data),
+    };
+    match fields.get_mut(secret) {
+        Some(field) => *field = REDACTED,
+        None => return Cow::Borrowed(data),
+    }
+
+    // Redacting a short password makes the packet longer, which may no longer fit in
+    // its length byte; rather than recording a broken packet it's kept as is.
+    let body = fields.join(&1);
+    let len = match u8::try_from(body.len() + 2) {
+        Ok(len) => len,
+        Err(_) => return Cow::Borrowed(data),
+    };
+    let mut redacted = Vec::with_capacity(body.len() + 3);
+    redacted.push(len);
+    redacted.push(packet_type);
+    redacted.extend_from_slice(&body);
+    redacted.push(0);
+    Cow::Owned(redacted)
+}
+
+/// Writes packets to a transcript as they're sent and received. Clones write to the
+/// same file, so the reader and writer of a connection can share it.
+#[derive(Clone, Debug)]
+pub(crate) struct Recorder {
+    file: Arc<Mutex<LineWriter<File>>>,
+}
+
+impl Recorder {
+    /// Start a new transcript at `path`, replacing an existing file.
+    pub(crate) fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file: Arc::new(Mutex::new(LineWriter::new(File::create(path)?))),
+        })
+    }
+
+    /// Record a packet. The transcript is only a debugging aid, so failing to
+    /// write to it doesn't end the session.
+    pub(crate) fn record(&self, direction: Direction, data: &[u8]) {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let data = match direction {
+            Direction::Sent => redact(data),
+            Direction::Received => Cow::Borrowed(data),
+        };
+        let hex: String = data.iter().map(|b| format!("{:02x}", b)).collect();
+        let line = format!(
+            "{}.{:06} {} {}\n",
+            time.as_secs(),
+            time.subsec_micros(),
+            direction.tag(),
+            hex
+        );
+
+        if let Ok(mut file) = self.file.lock() {
+            file.write_all(line.as_bytes()).ok();
+        }
+    }
+
+    /// Record a frame that was received, restoring its length byte.
+    pub(crate) fn record_frame(&self, frame: &Frame) {
+        let len = if frame.extended {
+            EXTENDED
+        } else {
+            frame.data.len() as u8
+        };
+
+        let mut data = Vec::with_capacity(frame.data.len() + 1);
+        data.push(len);
+        data.extend_from_slice(&frame.data);
+        self.record(Direction::Received, &data);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packets::{ClientPacket, LOGIN_LOGIN};
+
+    fn login(password: Option<&str>) -> ClientPacket {
+        ClientPacket::Login {
+            login_id: "alice".to_string(),
+            nickname: "alice".to_string(),
+            group: "rust".to_string(),
+            command: LOGIN_LOGIN.to_string(),
+            password: password.map(str::to_string),
+        }
+    }
+
+    fn redacted(packet: &ClientPacket) -> ClientPacket {
+        let data = packet.encode().unwrap();
+        ClientPacket::decode(&redact(&data)[1..]).unwrap()
+    }
+
+    #[test]
+    fn login_password_is_redacted() {
+        assert_eq!(redacted(&login(Some("secret"))), login(Some("********")));
+        assert_eq!(redacted(&login(None)), login(None));
+    }
+
+    #[test]
+    fn password_command_is_redacted() {
+        let packet = ClientPacket::command(CMD_P, "secret");
+        assert_eq!(redacted(&packet), ClientPacket::command(CMD_P, "********"));
+
+        let packet = ClientPacket::command("m", "server p secret");
+        assert_eq!(redacted(&packet), packet);
+        let packet = ClientPacket::Open("secret".to_string());
+        assert_eq!(redacted(&packet), packet);
+    }
+
+    #[test]
+    fn packet_too_long_to_redact_is_kept() {
+        let packet = ClientPacket::Login {
+            login_id: "a".repeat(120),
+            nickname: "b".repeat(120),
+            group: "g".to_string(),
+            command: LOGIN_LOGIN.to_string(),
+            password: Some("p".to_string()),
+        };
+        let data = packet.encode().unwrap();
+        assert_eq!(redact(&data), Cow::Borrowed(&data[..]));
+    }
+
+    #[test]
+    fn recorded_transcript_has_no_passwords() {
+        let path = std::env::temp_dir().join(format!("icb-transcript-test-{}", std::process::id()));
+        let recorder = Recorder::create(&path).unwrap();
+        let login = login(Some("secret")).encode().unwrap();
+        recorder.record(Direction::Sent, &login);
+        recorder.record(Direction::Received, b"\x02a\x00");
+        drop(recorder);
+
+        let entries = load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].direction, Direction::Sent);
+        let data = &entries[0].data;
+        assert!(!data.windows(6).any(|w| w == b"secret"));
+        assert_eq!(usize::from(data[0]), data.len() - 1);
+        assert_eq!(entries[1].data, b"\x02a\x00");
+    }
+}
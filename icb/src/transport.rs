@@ -0,0 +1,132 @@
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, TcpStream};
+#[cfg(feature = "tls")]
+use std::sync::MutexGuard;
+use std::sync::{Arc, Mutex};
+
+#[cfg(feature = "tls")]
+use rustls::ClientConnection;
+
+/// The connection to the server, either plain or over TLS, or a transcript being
+/// replayed.
+///
+/// A `Stream` can be cloned so one thread can block reading from it while another
+/// writes to it.
+#[derive(Debug)]
+pub(crate) enum Stream {
+    Plain(TcpStream),
+    /// The TLS state is shared between the clones; it's only locked while records are
+    /// processed, not while waiting for data.
+    #[cfg(feature = "tls")]
+    Tls {
+        sock: TcpStream,
+        conn: Arc<Mutex<ClientConnection>>,
+    },
+    /// Data received in a recorded transcript, see `Config::replay`.
+    Replay(Arc<Mutex<io::Cursor<Vec<u8>>>>),
+}
+
+impl Stream {
+    pub(crate) fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Plain(sock) => Ok(Stream::Plain(sock.try_clone()?)),
+            #[cfg(feature = "tls")]
+            Stream::Tls { sock, conn } => Ok(Stream::Tls {
+                sock: sock.try_clone()?,
+                conn: conn.clone(),
+            }),
+            Stream::Replay(data) => Ok(Stream::Replay(data.clone())),
+        }
+    }
+
+    /// Close the connection, which also ends any blocking reads on its clones.
+    pub(crate) fn shutdown(&self) -> io::Result<()> {
+        match self {
+            Stream::Plain(sock) => sock.shutdown(Shutdown::Both),
+            #[cfg(feature = "tls")]
+            Stream::Tls { sock, conn } => {
+                // Let the server know we're done, if it's still listening.
+                if let Ok(mut conn) = lock(conn) {
+                    conn.send_close_notify();
+                    conn.write_tls(&mut &*sock).ok();
+                }
+                sock.shutdown(Shutdown::Both)
+            }
+            Stream::Replay(_) => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "tls")]
+fn lock(conn: &Mutex<ClientConnection>) -> io::Result<MutexGuard<'_, ClientConnection>> {
+    conn.lock()
+        .map_err(|_| io::Error::other("TLS connection state is poisoned"))
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(sock) => sock.read(buf),
+            Stream::Replay(data) => data
+                .lock()
+                .map_err(|_| io::Error::other("transcript is poisoned"))?
+                .read(buf),
+            #[cfg(feature = "tls")]
+            Stream::Tls { sock, conn } => loop {
+                // Hand out whatever was decrypted already, which is 0 bytes once the
+                // server closed the connection.
+                match lock(conn)?.reader().read(buf) {
+                    Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                    res => return res,
+                }
+
+                let mut chunk = [0; 4096];
+                let nbytes = sock.read(&mut chunk)?;
+
+                let mut conn = lock(conn)?;
+                let mut data = &chunk[..nbytes];
+                loop {
+                    conn.read_tls(&mut data)?;
+                    conn.process_new_packets()
+                        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                    if data.is_empty() {
+                        break;
+                    }
+                }
+
+                // Processing may have produced a response, e.g. an alert.
+                while conn.wants_write() {
+                    conn.write_tls(&mut &*sock)?;
+                }
+            },
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Plain(sock) => sock.write(buf),
+            // There's nobody to send anything to.
+            Stream::Replay(_) => Ok(buf.len()),
+            #[cfg(feature = "tls")]
+            Stream::Tls { sock, conn } => {
+                let mut conn = lock(conn)?;
+                let nbytes = conn.writer().write(buf)?;
+                while conn.wants_write() {
+                    conn.write_tls(&mut &*sock)?;
+                }
+                Ok(nbytes)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Plain(sock) => sock.flush(),
+            #[cfg(feature = "tls")]
+            Stream::Tls { .. } => Ok(()),
+            Stream::Replay(_) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,74 @@
+/// A user as reported in a who-listing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct UserInfo {
+    pub nickname: String,
+    /// Whether this user is the moderator of the group.
+    pub moderator: bool,
+    /// Number of seconds since the user was last active.
+    pub idle: u64,
+    /// Time the user logged in, in seconds since the epoch.
+    pub login_time: i64,
+    pub username: String,
+    pub hostname: String,
+    /// Whether the nickname has been registered with the server.
+    pub registered: bool,
+}
+
+/// A group and its members as reported in a who-listing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct GroupInfo {
+    pub name: String,
+    pub topic: Option<String>,
+    pub moderator: Option<String>,
+    pub members: Vec<UserInfo>,
+}
+
+impl GroupInfo {
+    /// Parse the group header line the server sends as generic command output, e.g.:
+    ///   "Group: hackers  (rvl) Mod: jasper        Topic: (None)"
+    pub(crate) fn from_header(line: &str) -> Option<GroupInfo> {
+        let rest = line.strip_prefix("Group: ")?;
+        let name = rest.split_whitespace().next()?.to_string();
+
+        let moderator = rest
+            .find("Mod: ")
+            .and_then(|i| rest[i + 5..].split_whitespace().next())
+            .filter(|m| *m != "(None)")
+            .map(|m| m.to_string());
+
+        let topic = rest
+            .find("Topic: ")
+            .map(|i| rest[i + 7..].trim())
+            .filter(|t| !t.is_empty() && *t != "(None)")
+            .map(|t| t.to_string());
+
+        Some(GroupInfo {
+            name,
+            topic,
+            moderator,
+            members: vec![],
+        })
+    }
+}
+
+/// The result of a `Command::Who`, with all users grouped per group.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct WhoListing {
+    pub groups: Vec<GroupInfo>,
+}
+
+impl WhoListing {
+    /// Add a user to the most recently listed group.
+    pub(crate) fn add_user(&mut self, user: UserInfo) {
+        if self.groups.is_empty() {
+            // Not all servers send a group header, so collect them in an anonymous group.
+            self.groups.push(GroupInfo::default());
+        }
+
+        let group = self.groups.last_mut().unwrap();
+        if user.moderator {
+            group.moderator = Some(user.nickname.clone());
+        }
+        group.members.push(user);
+    }
+}
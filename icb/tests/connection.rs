@@ -0,0 +1,131 @@
+use std::future::poll_fn;
+use std::pin::Pin;
+use std::time::Duration;
+
+use futures_core::Stream;
+use icb::packets::ClientPacket;
+use icb::testing::{MockConfig, MockServer};
+use icb::{Command, Config, DisconnectReason, Error, Event, IcbConnection, ReconnectPolicy};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The next event from the connection, failing the test if none arrives in time.
+async fn next_event(conn: &mut IcbConnection) -> Option<Event> {
+    tokio::time::timeout(TIMEOUT, poll_fn(|cx| Pin::new(&mut *conn).poll_next(cx)))
+        .await
+        .expect("timed out waiting for an event")
+}
+
+/// The next packet received by the mock server, without blocking the runtime.
+async fn next_packet(mock: &MockServer) -> ClientPacket {
+    let deadline = tokio::time::Instant::now() + TIMEOUT;
+    loop {
+        if let Some(packet) = mock.next_packet(Duration::ZERO) {
+            return packet;
+        }
+        assert!(
+            tokio::time::Instant::now() < deadline,
+            "timed out waiting for a packet"
+        );
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+}
+
+#[tokio::test]
+async fn session() {
+    let mock = MockServer::start().unwrap();
+    let mut conn = IcbConnection::connect(mock.config("tester")).await.unwrap();
+
+    assert!(matches!(
+        next_event(&mut conn).await,
+        Some(Event::Protocol { .. })
+    ));
+    assert!(matches!(
+        next_packet(&mock).await,
+        ClientPacket::Login { nickname, .. } if nickname == "tester"
+    ));
+
+    mock.open("alice", "hello");
+    match next_event(&mut conn).await {
+        Some(Event::Open { nick, text }) => {
+            assert_eq!(nick, "alice");
+            assert_eq!(text, "hello");
+        }
+        event => panic!("expected an open message, got {:?}", event),
+    }
+
+    conn.send(Command::Open("hi there".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(
+        next_packet(&mock).await,
+        ClientPacket::Open("hi there".to_string())
+    );
+
+    conn.send(Command::Bye).await.unwrap();
+    assert!(matches!(
+        next_event(&mut conn).await,
+        Some(Event::Disconnected(DisconnectReason::Quit))
+    ));
+    assert!(next_event(&mut conn).await.is_none());
+}
+
+#[tokio::test]
+async fn disconnected_by_server() {
+    let mock = MockServer::start().unwrap();
+    let mut conn = IcbConnection::connect(mock.config("tester")).await.unwrap();
+    next_packet(&mock).await;
+
+    mock.disconnect();
+    loop {
+        match next_event(&mut conn).await {
+            Some(Event::Disconnected(DisconnectReason::Error(e))) => {
+                assert!(matches!(*e, Error::Disconnected | Error::Io(_)));
+                break;
+            }
+            Some(_) => continue,
+            None => panic!("stream ended without a disconnect event"),
+        }
+    }
+}
+
+#[tokio::test]
+async fn login_rejected() {
+    let mock = MockServer::start_with(MockConfig {
+        login_error: Some("Nickname already in use.".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    match IcbConnection::connect(mock.config("tester")).await {
+        Err(Error::LoginRejected { reason }) => assert_eq!(reason, "Nickname already in use."),
+        res => panic!(
+            "expected the login to be rejected, got {:?}",
+            res.map(|_| ())
+        ),
+    }
+}
+
+#[tokio::test]
+async fn unsupported_options_are_rejected() {
+    let configs = vec![
+        Config {
+            reconnect: Some(ReconnectPolicy::default()),
+            ..Default::default()
+        },
+        Config {
+            proxy: Some("socks5://localhost:1080".parse().unwrap()),
+            ..Default::default()
+        },
+        Config {
+            replay: Some("session.log".into()),
+            ..Default::default()
+        },
+    ];
+    for config in configs {
+        assert!(matches!(
+            IcbConnection::connect(config).await,
+            Err(Error::Unsupported(_))
+        ));
+    }
+}
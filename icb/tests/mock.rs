@@ -0,0 +1,536 @@
+use std::thread;
+use std::time::Duration;
+
+use icb::packets::{
+    ClientPacket, CommandOutput, ServerPacket, CMD_BEEP, CMD_MSG, CMD_NOBEEP, CMD_P,
+};
+use icb::testing::{MockConfig, MockServer};
+use icb::{
+    Client, Command, DisconnectReason, Error, Event, NoBeepMode, ReconnectPolicy, StatusCategory,
+    UserInfo,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Log in to `mock` and run the session in the background.
+fn connect(mock: &MockServer) -> (Client, thread::JoinHandle<()>) {
+    let (client, mut server) = icb::init(mock.config("tester")).unwrap();
+    let handle = thread::spawn(move || server.run());
+    (client, handle)
+}
+
+/// Log in to `mock` with a quick reconnect policy and run the session in the background.
+fn connect_reconnecting(
+    mock: &MockServer,
+    initial_delay: Duration,
+    max_attempts: u32,
+) -> (Client, thread::JoinHandle<()>) {
+    let config = icb::Config {
+        reconnect: Some(ReconnectPolicy {
+            max_attempts,
+            initial_delay,
+            max_delay: initial_delay,
+        }),
+        ..mock.config("tester")
+    };
+    let (client, mut server) = icb::init(config).unwrap();
+    let handle = thread::spawn(move || server.run());
+    (client, handle)
+}
+
+/// The next event other than the protocol information sent upon connecting.
+fn next_event(client: &Client) -> Event {
+    loop {
+        match client.msg_r.recv_timeout(TIMEOUT).expect("no event") {
+            Event::Protocol { .. } => continue,
+            event => return event,
+        }
+    }
+}
+
+fn quit(client: Client, handle: thread::JoinHandle<()>) {
+    client.cmd_s.send(Command::Bye).unwrap();
+    assert!(matches!(
+        next_event(&client),
+        Event::Disconnected(DisconnectReason::Quit)
+    ));
+    handle.join().unwrap();
+}
+
+#[test]
+fn login() {
+    let mock = MockServer::start().unwrap();
+    let (client, mut server) = icb::init(mock.config("tester")).unwrap();
+
+    match client.msg_r.recv_timeout(TIMEOUT).unwrap() {
+        Event::Protocol { server_id, .. } => assert_eq!(server_id, "icb-rs mock server"),
+        event => panic!("expected protocol information, got {:?}", event),
+    }
+    match mock.next_packet(TIMEOUT).unwrap() {
+        ClientPacket::Login {
+            login_id,
+            nickname,
+            group,
+            command,
+            password,
+        } => {
+            assert_eq!(login_id, "tester");
+            assert_eq!(nickname, "tester");
+            assert_eq!(group, "1");
+            assert_eq!(command, "login");
+            assert_eq!(password, None);
+        }
+        packet => panic!("expected a login packet, got {:?}", packet),
+    }
+
+    let handle = thread::spawn(move || server.run());
+    quit(client, handle);
+}
+
+#[test]
+fn login_with_password() {
+    let mock = MockServer::start().unwrap();
+    let config = icb::Config {
+        login_id: Some("account".to_string()),
+        password: Some("secret".to_string()),
+        ..mock.config("tester")
+    };
+    // The password is kept out of debug output.
+    assert!(!format!("{:?}", config).contains("secret"));
+    let (client, mut server) = icb::init(config).unwrap();
+
+    match mock.next_packet(TIMEOUT).unwrap() {
+        ClientPacket::Login {
+            login_id,
+            nickname,
+            password,
+            ..
+        } => {
+            assert_eq!(login_id, "account");
+            assert_eq!(nickname, "tester");
+            assert_eq!(password.as_deref(), Some("secret"));
+        }
+        packet => panic!("expected a login packet, got {:?}", packet),
+    }
+    assert!(!format!("{:?}", server).contains("secret"));
+
+    let handle = thread::spawn(move || server.run());
+    quit(client, handle);
+}
+
+#[test]
+fn login_rejected() {
+    let mock = MockServer::start_with(MockConfig {
+        login_error: Some("Nickname already in use.".to_string()),
+        ..Default::default()
+    })
+    .unwrap();
+
+    match icb::init(mock.config("tester")) {
+        Err(Error::LoginRejected { reason }) => assert_eq!(reason, "Nickname already in use."),
+        res => panic!(
+            "expected the login to be rejected, got {:?}",
+            res.map(|_| ())
+        ),
+    }
+    assert_eq!(mock.received().len(), 1);
+}
+
+#[test]
+fn injected_packets_become_events() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+
+    mock.open("alice", "hello group");
+    match next_event(&client) {
+        Event::Open { nick, text } => {
+            assert_eq!((nick, text), ("alice".into(), "hello group".into()))
+        }
+        event => panic!("expected an open message, got {:?}", event),
+    }
+
+    mock.personal("bob", "hello you");
+    match next_event(&client) {
+        Event::Personal { nick, text } => {
+            assert_eq!((nick, text), ("bob".into(), "hello you".into()))
+        }
+        event => panic!("expected a personal message, got {:?}", event),
+    }
+
+    mock.status(StatusCategory::Arrive, "carol entered group");
+    match next_event(&client) {
+        Event::Status { category, text } => {
+            assert_eq!(category, StatusCategory::Arrive);
+            assert_eq!(text, "carol entered group");
+        }
+        event => panic!("expected a status message, got {:?}", event),
+    }
+
+    mock.error("No such user");
+    match next_event(&client) {
+        Event::Error(message) => assert_eq!(message, "No such user"),
+        event => panic!("expected an error, got {:?}", event),
+    }
+
+    mock.command_output(CommandOutput::Output("The topic is: rust".to_string()));
+    match next_event(&client) {
+        Event::CommandOutput(CommandOutput::Output(message)) => {
+            assert_eq!(message, "The topic is: rust")
+        }
+        event => panic!("expected command output, got {:?}", event),
+    }
+
+    quit(client, handle);
+}
+
+#[test]
+fn important_message() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+
+    mock.send(ServerPacket::Important {
+        category: StatusCategory::Other("Shutdown".to_string()),
+        message: "Server going down in 5 minutes".to_string(),
+    });
+    match next_event(&client) {
+        Event::Important { category, text } => {
+            assert_eq!(category, StatusCategory::Other("Shutdown".to_string()));
+            assert_eq!(text, "Server going down in 5 minutes");
+        }
+        event => panic!("expected an important message, got {:?}", event),
+    }
+
+    quit(client, handle);
+}
+
+#[test]
+fn exit_ends_session() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+
+    mock.send(ServerPacket::Exit);
+    assert!(matches!(
+        next_event(&client),
+        Event::Disconnected(DisconnectReason::Exit)
+    ));
+    handle.join().unwrap();
+    assert!(client.msg_r.try_recv().is_err());
+}
+
+#[test]
+fn server_ping_is_answered() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+    mock.next_packet(TIMEOUT).unwrap();
+
+    mock.send(ServerPacket::Ping("42".to_string()));
+    assert_eq!(
+        mock.next_packet(TIMEOUT),
+        Some(ClientPacket::Pong("42".to_string()))
+    );
+
+    quit(client, handle);
+}
+
+#[test]
+fn ping_round_trip() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+    mock.next_packet(TIMEOUT).unwrap();
+
+    client.cmd_s.send(Command::Ping).unwrap();
+    let id = match mock.next_packet(TIMEOUT).unwrap() {
+        ClientPacket::Ping(id) => id,
+        packet => panic!("expected a ping, got {:?}", packet),
+    };
+
+    // Pongs which don't answer a ping of the client are ignored.
+    mock.send(ServerPacket::Pong("unknown".to_string()));
+    mock.send(ServerPacket::Pong(id.clone()));
+    match next_event(&client) {
+        Event::Pong { id: pong_id, .. } => assert_eq!(pong_id, id),
+        event => panic!("expected a pong, got {:?}", event),
+    }
+
+    quit(client, handle);
+}
+
+#[test]
+fn who_listing() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+
+    client.cmd_s.send(Command::Who(None)).unwrap();
+    // Wait for the command, so the listing isn't sent before it's expected.
+    mock.next_packet(TIMEOUT).unwrap();
+    mock.next_packet(TIMEOUT).unwrap();
+
+    let user = UserInfo {
+        nickname: "alice".to_string(),
+        moderator: true,
+        idle: 10,
+        login_time: 1_700_000_000,
+        username: "alice".to_string(),
+        hostname: "example.net".to_string(),
+        registered: true,
+    };
+    mock.command_output(CommandOutput::WhoHeader);
+    mock.command_output(CommandOutput::GroupHeader {
+        name: "rust".to_string(),
+        topic: Some("crabs".to_string()),
+    });
+    mock.command_output(CommandOutput::WhoLine(user.clone()));
+    mock.command_output(CommandOutput::End(None));
+
+    match next_event(&client) {
+        Event::WhoListing(listing) => {
+            assert_eq!(listing.groups.len(), 1);
+            assert_eq!(listing.groups[0].name, "rust");
+            assert_eq!(listing.groups[0].topic.as_deref(), Some("crabs"));
+            assert_eq!(listing.groups[0].moderator.as_deref(), Some("alice"));
+            assert_eq!(listing.groups[0].members, vec![user]);
+        }
+        event => panic!("expected a who-listing, got {:?}", event),
+    }
+
+    quit(client, handle);
+}
+
+#[test]
+fn client_packets_are_received() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+
+    client
+        .cmd_s
+        .send(Command::Open("hi all".to_string()))
+        .unwrap();
+    client
+        .cmd_s
+        .send(Command::Personal("alice".to_string(), "hi".to_string()))
+        .unwrap();
+    client.cmd_s.send(Command::Beep("bob".to_string())).unwrap();
+    quit(client, handle);
+
+    // Everything the client sent was read before the connection closed.
+    let deadline = std::time::Instant::now() + TIMEOUT;
+    while mock.received().len() < 4 && std::time::Instant::now() < deadline {
+        thread::sleep(Duration::from_millis(10));
+    }
+    let received = mock.received();
+    assert!(matches!(received[0], ClientPacket::Login { .. }));
+    assert_eq!(
+        received[1..],
+        [
+            ClientPacket::Open("hi all".to_string()),
+            ClientPacket::command(CMD_MSG, "alice hi"),
+            ClientPacket::command(CMD_BEEP, "bob"),
+        ]
+    );
+}
+
+#[test]
+fn register_nobeep_and_raw_commands() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+    mock.next_packet(TIMEOUT).unwrap();
+
+    let commands = vec![
+        (
+            Command::Register("secret".to_string()),
+            ClientPacket::command(CMD_P, "secret"),
+        ),
+        (
+            Command::NoBeep(NoBeepMode::Verbose),
+            ClientPacket::command(CMD_NOBEEP, "verbose"),
+        ),
+        (
+            Command::Raw {
+                cmd: "m".to_string(),
+                args: "alice hi".to_string(),
+            },
+            ClientPacket::command("m", "alice hi"),
+        ),
+        // Raw commands aren't limited to the ones this library knows about.
+        (
+            Command::Raw {
+                cmd: "motd".to_string(),
+                args: String::new(),
+            },
+            ClientPacket::command("motd", ""),
+        ),
+    ];
+    for (command, packet) in commands {
+        client.cmd_s.send(command).unwrap();
+        assert_eq!(mock.next_packet(TIMEOUT), Some(packet));
+    }
+    quit(client, handle);
+}
+
+#[test]
+fn disconnected_by_server() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect(&mock);
+    mock.next_packet(TIMEOUT).unwrap();
+
+    mock.disconnect();
+    match next_event(&client) {
+        Event::Disconnected(DisconnectReason::Error(e)) => {
+            assert!(matches!(*e, Error::Disconnected | Error::Io(_)), "{}", e)
+        }
+        event => panic!("expected to be disconnected, got {:?}", event),
+    }
+    handle.join().unwrap();
+}
+
+#[test]
+fn reconnect_with_confirmed_nickname_and_group() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect_reconnecting(&mock, Duration::from_millis(20), 3);
+    mock.next_packet(TIMEOUT).unwrap();
+
+    client
+        .cmd_s
+        .send(Command::Name("newname".to_string()))
+        .unwrap();
+    client
+        .cmd_s
+        .send(Command::Group("rust".to_string()))
+        .unwrap();
+    mock.next_packet(TIMEOUT).unwrap();
+    mock.next_packet(TIMEOUT).unwrap();
+    mock.status(StatusCategory::Name, "tester changed nickname to newname");
+    mock.status(StatusCategory::Status, "You are now in group rust");
+    for _ in 0..2 {
+        assert!(matches!(next_event(&client), Event::Status { .. }));
+    }
+
+    mock.disconnect();
+    match next_event(&client) {
+        Event::Reconnecting { attempt, delay } => {
+            assert_eq!(attempt, 1);
+            assert!(delay >= Duration::from_millis(10) && delay <= Duration::from_millis(20));
+        }
+        event => panic!("expected to be reconnecting, got {:?}", event),
+    }
+    assert!(matches!(next_event(&client), Event::Reconnected));
+
+    match mock.next_packet(TIMEOUT).unwrap() {
+        ClientPacket::Login {
+            login_id,
+            nickname,
+            group,
+            ..
+        } => {
+            assert_eq!(login_id, "tester");
+            assert_eq!(nickname, "newname");
+            assert_eq!(group, "rust");
+        }
+        packet => panic!("expected a login packet, got {:?}", packet),
+    }
+
+    // The session carries on over the new connection.
+    client
+        .cmd_s
+        .send(Command::Open("back".to_string()))
+        .unwrap();
+    assert_eq!(
+        mock.next_packet(TIMEOUT),
+        Some(ClientPacket::Open("back".to_string()))
+    );
+    quit(client, handle);
+}
+
+#[test]
+fn bye_while_waiting_to_reconnect() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect_reconnecting(&mock, Duration::from_secs(60), 3);
+    mock.next_packet(TIMEOUT).unwrap();
+
+    mock.disconnect();
+    assert!(matches!(
+        next_event(&client),
+        Event::Reconnecting { attempt: 1, .. }
+    ));
+
+    // Commands other than bye can't be sent in the meantime.
+    client.cmd_s.send(Command::Open("hi".to_string())).unwrap();
+    assert!(matches!(next_event(&client), Event::Error(_)));
+    quit(client, handle);
+}
+
+#[test]
+fn reconnect_gives_up() {
+    let mock = MockServer::start().unwrap();
+    let (client, handle) = connect_reconnecting(&mock, Duration::from_millis(10), 2);
+    mock.next_packet(TIMEOUT).unwrap();
+
+    // Without a server, every attempt fails.
+    drop(mock);
+    for expected in 1..=2 {
+        match next_event(&client) {
+            Event::Reconnecting { attempt, .. } => assert_eq!(attempt, expected),
+            event => panic!("expected to be reconnecting, got {:?}", event),
+        }
+    }
+    assert!(matches!(
+        next_event(&client),
+        Event::Disconnected(DisconnectReason::Error(_))
+    ));
+    handle.join().unwrap();
+}
+
+#[test]
+fn record_and_replay() {
+    let path = std::env::temp_dir().join(format!("icb-replay-test-{}", std::process::id()));
+    let mock = MockServer::start().unwrap();
+    let config = icb::Config {
+        password: Some("secret".to_string()),
+        transcript: Some(path.clone()),
+        ..mock.config("tester")
+    };
+    let (client, mut server) = icb::init(config).unwrap();
+    let handle = thread::spawn(move || server.run());
+
+    mock.open("alice", "hello");
+    mock.personal("bob", "psst");
+    for _ in 0..2 {
+        assert!(matches!(
+            next_event(&client),
+            Event::Open { .. } | Event::Personal { .. }
+        ));
+    }
+    quit(client, handle);
+
+    let entries = icb::transcript::load(&path).unwrap();
+    assert!(entries
+        .iter()
+        .all(|entry| !entry.data.windows(6).any(|w| w == b"secret")));
+
+    // Playing the transcript back delivers the same events, after which it ends.
+    let config = icb::Config {
+        nickname: "tester".to_string(),
+        replay: Some(path.clone()),
+        ..Default::default()
+    };
+    let (client, mut server) = icb::init(config).unwrap();
+    let handle = thread::spawn(move || server.run());
+    match next_event(&client) {
+        Event::Open { nick, text } => {
+            assert_eq!((nick.as_str(), text.as_str()), ("alice", "hello"))
+        }
+        event => panic!("expected an open message, got {:?}", event),
+    }
+    match next_event(&client) {
+        Event::Personal { nick, text } => {
+            assert_eq!((nick.as_str(), text.as_str()), ("bob", "psst"))
+        }
+        event => panic!("expected a personal message, got {:?}", event),
+    }
+    assert!(matches!(
+        next_event(&client),
+        Event::Disconnected(DisconnectReason::EndOfTranscript)
+    ));
+    handle.join().unwrap();
+    std::fs::remove_file(&path).ok();
+}
@@ -0,0 +1,375 @@
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use icb::packets::{ClientPacket, LOGIN_LOGIN};
+use icb::server::{IcbServer, ServerConfig, BOOT_GROUP, MAX_TOPIC_LEN};
+use icb::{Client, Command, Config, Error, Event, NoBeepMode, StatusCategory};
+
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Start a server on a free port.
+fn start() -> SocketAddr {
+    let server = IcbServer::bind(ServerConfig {
+        port: 0,
+        ..Default::default()
+    })
+    .unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    addr
+}
+
+fn config(addr: SocketAddr, nickname: &str, group: &str) -> Config {
+    Config {
+        serverip: addr.ip().to_string(),
+        port: addr.port(),
+        nickname: nickname.to_string(),
+        group: group.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Log in and run the session in the background, once the group has been joined.
+fn connect(addr: SocketAddr, nickname: &str, group: &str) -> Client {
+    let (client, mut server) = icb::init(config(addr, nickname, group)).unwrap();
+    thread::spawn(move || server.run());
+    expect(&client, |event| {
+        matches!(event, Event::Status { category: StatusCategory::Status, text }
+            if text.starts_with("You are now in group"))
+    });
+    client
+}
+
+/// Wait for an event matching `f`, skipping any others.
+fn expect<F: Fn(&Event) -> bool>(client: &Client, f: F) -> Event {
+    let deadline = Instant::now() + TIMEOUT;
+    loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        match client.msg_r.recv_timeout(remaining) {
+            Ok(event) if f(&event) => return event,
+            Ok(_) => continue,
+            Err(_) => panic!("timed out waiting for an event"),
+        }
+    }
+}
+
+fn expect_status(client: &Client, category: StatusCategory, text: &str) {
+    expect(
+        client,
+        |event| matches!(event, Event::Status { category: c, text: t } if *c == category && t == text),
+    );
+}
+
+fn expect_error(client: &Client, text: &str) {
+    expect(
+        client,
+        |event| matches!(event, Event::Error(e) if e == text),
+    );
+}
+
+fn send(client: &Client, command: Command) {
+    client.cmd_s.send(command).unwrap();
+}
+
+#[test]
+fn login_and_duplicate_nickname() {
+    let addr = start();
+    let alice = connect(addr, "alice", "rust");
+
+    match icb::init(config(addr, "ALICE", "rust")) {
+        Err(Error::LoginRejected { reason }) => assert_eq!(reason, "Nickname already in use."),
+        res => panic!(
+            "expected the login to be rejected, got {:?}",
+            res.map(|_| ())
+        ),
+    }
+
+    let _bob = connect(addr, "bob", "rust");
+    expect(&alice, |event| {
+        matches!(event, Event::Status { category: StatusCategory::SignOn, text }
+            if text.starts_with("bob ("))
+    });
+}
+
+#[test]
+fn group_with_whitespace_is_rejected() {
+    let addr = start();
+
+    match icb::init(config(addr, "alice", "g with space")) {
+        Err(Error::LoginRejected { reason }) => assert_eq!(reason, "Invalid group name."),
+        res => panic!(
+            "expected the login to be rejected, got {:?}",
+            res.map(|_| ())
+        ),
+    }
+
+    let alice = connect(addr, "alice", "rust");
+    send(&alice, Command::Group("g with space".to_string()));
+    expect_error(&alice, "Usage: g group");
+}
+
+#[test]
+fn open_and_personal_messages() {
+    let addr = start();
+    let alice = connect(addr, "alice", "rust");
+    let bob = connect(addr, "bob", "rust");
+    let carol = connect(addr, "carol", "other");
+
+    send(&alice, Command::Open("hello group".to_string()));
+    match expect(&bob, |event| matches!(event, Event::Open { .. })) {
+        Event::Open { nick, text } => {
+            assert_eq!(nick, "alice");
+            assert_eq!(text, "hello group");
+        }
+        _ => unreachable!(),
+    }
+
+    send(
+        &alice,
+        Command::Personal("carol".to_string(), "hello you".to_string()),
+    );
+    match expect(&carol, |event| {
+        matches!(event, Event::Open { .. } | Event::Personal { .. })
+    }) {
+        Event::Personal { nick, text } => {
+            assert_eq!(nick, "alice");
+            assert_eq!(text, "hello you");
+        }
+        event => panic!("expected a personal message, got {:?}", event),
+    }
+
+    send(
+        &alice,
+        Command::Personal("nobody".to_string(), "hi".to_string()),
+    );
+    expect_error(&alice, "nobody is not signed on.");
+}
+
+#[test]
+fn who_listing() {
+    let addr = start();
+    let alice = connect(addr, "alice", "rust");
+    let _bob = connect(addr, "bob", "rust");
+    let _carol = connect(addr, "carol", "other");
+
+    send(&alice, Command::Who(None));
+    let listing = match expect(&alice, |event| matches!(event, Event::WhoListing(_))) {
+        Event::WhoListing(listing) => listing,
+        _ => unreachable!(),
+    };
+
+    let groups: Vec<_> = listing
+        .groups
+        .iter()
+        .map(|g| {
+            let members: Vec<_> = g.members.iter().map(|m| m.nickname.as_str()).collect();
+            (g.name.as_str(), g.moderator.as_deref(), members)
+        })
+        .collect();
+    assert_eq!(
+        groups,
+        vec![
+            ("other", Some("carol"), vec!["carol"]),
+            ("rust", Some("alice"), vec!["alice", "bob"]),
+        ]
+    );
+
+    // The who login command lists the users without logging in.
+    let listing = icb::who(config(addr, "dave", "1")).unwrap();
+    assert_eq!(listing.groups.len(), 2);
+}
+
+#[test]
+fn nobeep_modes() {
+    let addr = start();
+    let alice = connect(addr, "alice", "rust");
+    let bob = connect(addr, "bob", "rust");
+
+    send(&bob, Command::NoBeep(NoBeepMode::On));
+    expect_status(&bob, StatusCategory::NoBeep, "No-Beep on");
+    send(&alice, Command::Beep("bob".to_string()));
+    expect_error(&alice, "bob has nobeep enabled.");
+
+    // In verbose mode bob hears about the attempt.
+    send(&bob, Command::NoBeep(NoBeepMode::Verbose));
+    expect_status(&bob, StatusCategory::NoBeep, "No-Beep verbose");
+    send(&alice, Command::Beep("bob".to_string()));
+    expect_error(&alice, "bob has nobeep enabled.");
+    expect_status(
+        &bob,
+        StatusCategory::NoBeep,
+        "alice attempted (and failed) to beep you",
+    );
+
+    send(&bob, Command::NoBeep(NoBeepMode::Off));
+    expect_status(&bob, StatusCategory::NoBeep, "No-Beep off");
+    send(&alice, Command::Beep("bob".to_string()));
+    expect(
+        &bob,
+        |event| matches!(event, Event::Beep { nick } if nick == "alice"),
+    );
+
+    send(
+        &bob,
+        Command::Raw {
+            cmd: "nobeep".to_string(),
+            args: "loud".to_string(),
+        },
+    );
+    expect_error(&bob, "Usage: nobeep on|off|verbose");
+}
+
+#[test]
+fn topic_requires_moderator() {
+    let addr = start();
+    let alice = connect(addr, "alice", "rust");
+    let bob = connect(addr, "bob", "rust");
+
+    send(&bob, Command::Topic("bob's topic".to_string()));
+    expect_error(&bob, "You aren't the moderator.");
+
+    send(&alice, Command::Topic("crabs".to_string()));
+    expect_status(
+        &bob,
+        StatusCategory::Topic,
+        "alice changed the topic to \"crabs\"",
+    );
+}
+
+#[test]
+fn long_topic() {
+    let addr = start();
+    let group = "g".repeat(60);
+    let alice = connect(addr, "alice", &group);
+
+    send(&alice, Command::Topic("x".repeat(230)));
+    expect_error(&alice, "Topic is longer than 160 characters.");
+
+    let topic = "y".repeat(MAX_TOPIC_LEN);
+    send(&alice, Command::Topic(topic.clone()));
+    expect_status(
+        &alice,
+        StatusCategory::Topic,
+        &format!("alice changed the topic to \"{}\"", topic),
+    );
+
+    // With a long group name the group header of the who-listing doesn't fit in a
+    // packet, so the topic is cut short.
+    send(&alice, Command::Who(None));
+    let listing = match expect(&alice, |event| matches!(event, Event::WhoListing(_))) {
+        Event::WhoListing(listing) => listing,
+        _ => unreachable!(),
+    };
+    assert_eq!(listing.groups.len(), 1);
+    let info = &listing.groups[0];
+    assert_eq!(info.name, group);
+    let listed = info.topic.as_deref().unwrap();
+    assert!(listed.len() < topic.len() && topic.starts_with(listed));
+    assert_eq!(info.members.len(), 1);
+}
+
+#[test]
+fn pass_moderation() {
+    let addr = start();
+    let alice = connect(addr, "alice", "rust");
+    let bob = connect(addr, "bob", "rust");
+
+    send(&bob, Command::Pass("alice".to_string()));
+    expect_error(&bob, "You aren't the moderator.");
+
+    send(&alice, Command::Pass("bob".to_string()));
+    expect_status(
+        &bob,
+        StatusCategory::Other("Pass".to_string()),
+        "alice has passed moderation to bob",
+    );
+
+    // Now bob is the moderator and alice isn't anymore.
+    send(&alice, Command::Topic("alice's topic".to_string()));
+    expect_error(&alice, "You aren't the moderator.");
+    send(&bob, Command::Topic("bob's topic".to_string()));
+    expect_status(
+        &alice,
+        StatusCategory::Topic,
+        "bob changed the topic to \"bob's topic\"",
+    );
+}
+
+#[test]
+fn boot_moves_to_idle_group() {
+    let addr = start();
+    let alice = connect(addr, "alice", "rust");
+    let bob = connect(addr, "bob", "rust");
+
+    send(&bob, Command::Boot("alice".to_string()));
+    expect_error(&bob, "You aren't the moderator.");
+
+    send(&alice, Command::Boot("bob".to_string()));
+    expect_status(&bob, StatusCategory::Boot, "You were booted by alice.");
+    expect_status(
+        &bob,
+        StatusCategory::Status,
+        &format!("You are now in group {} as moderator", BOOT_GROUP),
+    );
+    expect_status(&alice, StatusCategory::Boot, "bob was booted.");
+
+    send(&alice, Command::Who(Some(BOOT_GROUP.to_string())));
+    match expect(&alice, |event| matches!(event, Event::WhoListing(_))) {
+        Event::WhoListing(listing) => {
+            assert_eq!(listing.groups.len(), 1);
+            assert_eq!(listing.groups[0].members[0].nickname, "bob");
+        }
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn stuck_client_does_not_block_others() {
+    let addr = start();
+    let alice = connect(addr, "alice", "rust");
+    let bob = connect(addr, "bob", "rust");
+
+    // A client that logs in but never reads what it's sent.
+    let mut stuck = TcpStream::connect(addr).unwrap();
+    let login = ClientPacket::Login {
+        login_id: "stuck".to_string(),
+        nickname: "stuck".to_string(),
+        group: "rust".to_string(),
+        command: LOGIN_LOGIN.to_string(),
+        password: None,
+    };
+    stuck.write_all(&login.encode().unwrap()).unwrap();
+    expect(&bob, |event| {
+        matches!(event, Event::Status { category: StatusCategory::SignOn, text }
+            if text.starts_with("stuck ("))
+    });
+
+    // Far more than fits in the socket buffers and the queue of the stuck client.
+    let message = "x".repeat(240);
+    for _ in 0..40_000 {
+        send(&alice, Command::Open(message.clone()));
+    }
+    send(&alice, Command::Open("done".to_string()));
+
+    expect(&bob, |event| {
+        matches!(event, Event::Status { category: StatusCategory::SignOff, text }
+            if text.starts_with("stuck ("))
+    });
+    expect(
+        &bob,
+        |event| matches!(event, Event::Open { text, .. } if text == "done"),
+    );
+
+    // The stuck client was disconnected.
+    stuck.set_read_timeout(Some(TIMEOUT)).unwrap();
+    let mut buf = [0; 65536];
+    let closed = loop {
+        match stuck.read(&mut buf) {
+            Ok(0) | Err(_) => break true,
+            Ok(_) => continue,
+        }
+    };
+    assert!(closed);
+}
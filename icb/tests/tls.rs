@@ -0,0 +1,209 @@
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use icb::packets::{ClientPacket, ServerPacket};
+use icb::{Command, Config, DisconnectReason, Error, Event, TlsConfig};
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use rustls::{ServerConfig, ServerConnection, StreamOwned};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// An in-process TLS server with a self-signed certificate for "localhost".
+struct TlsServer {
+    port: u16,
+    ca_file: PathBuf,
+    handle: thread::JoinHandle<io::Result<Vec<ClientPacket>>>,
+}
+
+impl TlsServer {
+    /// Accept a single client, log it in and send it `packets`. The packets the client
+    /// sends are returned once it disconnects.
+    fn start(name: &str, packets: Vec<ServerPacket>) -> TlsServer {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        let ca_file =
+            std::env::temp_dir().join(format!("icb-tls-test-{}-{}.pem", name, std::process::id()));
+        std::fs::write(&ca_file, certified.cert.pem()).unwrap();
+
+        let config =
+            ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .unwrap()
+                .with_no_client_auth()
+                .with_single_cert(
+                    vec![CertificateDer::from(certified.cert.der().to_vec())],
+                    PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(
+                        certified.key_pair.serialize_der(),
+                    )),
+                )
+                .unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let handle = thread::spawn(move || {
+            let (sock, _) = listener.accept()?;
+            sock.set_read_timeout(Some(TIMEOUT))?;
+            let conn = ServerConnection::new(Arc::new(config))
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            serve(StreamOwned::new(conn, sock), packets)
+        });
+
+        TlsServer {
+            port,
+            ca_file,
+            handle,
+        }
+    }
+
+    fn config(&self, tls: TlsConfig) -> Config {
+        Config {
+            serverip: "127.0.0.1".to_string(),
+            port: self.port,
+            nickname: "tester".to_string(),
+            tls: Some(TlsConfig {
+                server_name: Some("localhost".to_string()),
+                ..tls
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// Wait for the client to disconnect and return the packets it sent.
+    fn received(self) -> io::Result<Vec<ClientPacket>> {
+        let received = self.handle.join().unwrap();
+        std::fs::remove_file(&self.ca_file).ok();
+        received
+    }
+}
+
+fn serve(
+    mut stream: StreamOwned<ServerConnection, TcpStream>,
+    packets: Vec<ServerPacket>,
+) -> io::Result<Vec<ClientPacket>> {
+    let protocol = ServerPacket::Protocol {
+        level: "1".to_string(),
+        host_id: "localhost".to_string(),
+        server_id: "tls test".to_string(),
+    };
+    stream.write_all(&protocol.encode().unwrap())?;
+
+    let mut received = vec![read_packet(&mut stream)?];
+    stream.write_all(&ServerPacket::Login.encode().unwrap())?;
+
+    // Send everything at once, so it arrives in as few records as possible.
+    let data: Vec<u8> = packets
+        .iter()
+        .flat_map(|packet| packet.encode().unwrap())
+        .collect();
+    stream.write_all(&data)?;
+    stream.flush()?;
+
+    loop {
+        match read_packet(&mut stream) {
+            Ok(packet) => received.push(packet),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(received)
+}
+
+fn read_packet<R: Read>(reader: &mut R) -> io::Result<ClientPacket> {
+    let mut len = [0; 1];
+    reader.read_exact(&mut len)?;
+    let mut data = vec![0; usize::from(len[0])];
+    reader.read_exact(&mut data)?;
+    Ok(ClientPacket::decode(&data)?)
+}
+
+fn open(nickname: &str, message: &str) -> ServerPacket {
+    ServerPacket::Open {
+        nickname: nickname.to_string(),
+        message: message.to_string(),
+    }
+}
+
+#[test]
+fn ca_file() {
+    let packets: Vec<ServerPacket> = (0..50)
+        .map(|i| open("alice", &format!("message {} {}", i, "x".repeat(200))))
+        .collect();
+    let server = TlsServer::start("ca-file", packets);
+
+    let (client, mut session) = icb::init(server.config(TlsConfig {
+        ca_file: Some(server.ca_file.clone()),
+        ..Default::default()
+    }))
+    .unwrap();
+    let run = thread::spawn(move || session.run());
+
+    let mut opens = 0;
+    while opens < 50 {
+        match client.msg_r.recv_timeout(TIMEOUT).unwrap() {
+            Event::Open { nick, text } => {
+                assert_eq!(nick, "alice");
+                assert!(text.starts_with(&format!("message {} ", opens)));
+                opens += 1;
+            }
+            Event::Protocol { .. } => {}
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    client
+        .cmd_s
+        .send(Command::Open("over tls".to_string()))
+        .unwrap();
+    client.cmd_s.send(Command::Bye).unwrap();
+    assert!(matches!(
+        client.msg_r.recv_timeout(TIMEOUT).unwrap(),
+        Event::Disconnected(DisconnectReason::Quit)
+    ));
+    run.join().unwrap();
+
+    let received = server.received().unwrap();
+    assert!(matches!(received[0], ClientPacket::Login { .. }));
+    assert_eq!(received[1..], [ClientPacket::Open("over tls".to_string())]);
+}
+
+#[test]
+fn untrusted_certificate() {
+    let server = TlsServer::start("untrusted", Vec::new());
+
+    // The self-signed certificate isn't among the web PKI roots.
+    match icb::init(server.config(TlsConfig::default())) {
+        Err(Error::Tls(_)) | Err(Error::Io(_)) => {}
+        Err(e) => panic!("expected a TLS error, got {:?}", e),
+        Ok(_) => panic!("connected despite an untrusted certificate"),
+    }
+    assert!(server.received().is_err());
+}
+
+#[test]
+fn insecure() {
+    let server = TlsServer::start("insecure", vec![ServerPacket::Exit]);
+
+    let (client, mut session) = icb::init(server.config(TlsConfig {
+        insecure: true,
+        ..Default::default()
+    }))
+    .unwrap();
+    let run = thread::spawn(move || session.run());
+
+    loop {
+        match client.msg_r.recv_timeout(TIMEOUT).unwrap() {
+            Event::Disconnected(reason) => {
+                assert!(matches!(reason, DisconnectReason::Exit));
+                break;
+            }
+            _ => continue,
+        }
+    }
+    run.join().unwrap();
+
+    let received = server.received().unwrap();
+    assert_eq!(received.len(), 1);
+}
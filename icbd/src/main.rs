@@ -0,0 +1,36 @@
+#[macro_use]
+extern crate clap;
+
+use clap::App;
+use icb::server::{IcbServer, ServerConfig};
+
+fn main() {
+    let clap_yaml = load_yaml!("clap.yml");
+    let matches = App::from_yaml(clap_yaml).get_matches();
+
+    let mut config = ServerConfig {
+        address: matches.value_of("address").unwrap().to_string(),
+        port: value_t!(matches, "port", u16).unwrap_or_else(|e| e.exit()),
+        default_group: matches.value_of("group").unwrap().to_string(),
+        ..Default::default()
+    };
+    if let Some(host_id) = matches.value_of("hostid") {
+        config.host_id = host_id.to_string();
+    }
+
+    let server = match IcbServer::bind(config.clone()) {
+        Ok(server) => server,
+        Err(e) => {
+            eprintln!(
+                "Could not listen on {}:{}: {}",
+                config.address, config.port, e
+            );
+            std::process::exit(1);
+        }
+    };
+
+    if let Ok(addr) = server.local_addr() {
+        println!("Listening on {}", addr);
+    }
+    server.run();
+}